@@ -0,0 +1,66 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use rand::Rng;
+
+use rtiow::camera::Camera;
+use rtiow::hittable::bvh::BvhNode;
+use rtiow::hittable::{HittableList, Sphere};
+use rtiow::material::{Lambertian, Material, Metal};
+use rtiow::random::new_rng;
+use rtiow::vec3::color::Color;
+use rtiow::vec3::Vec3;
+
+// Compare render times for a scene of over 10,000 spheres with and without a BVH.
+//  Run with: cargo run --release --example bvh_benchmark
+fn main() {
+  let image_width = 160;
+  let image_height = 90;
+
+  //Create the World: a grid of 101 x 101 small spheres on a large ground sphere
+  let mut rng = new_rng(1);
+  let ground: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+  let mut world = HittableList::new_empty();
+  world.add(Arc::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+  for a in -50..=50 {
+    for b in -50..=50 {
+      let center = Vec3::new(a as f64 * 0.4, 0.1, b as f64 * 0.4);
+      let albedo = Color::new(rng.gen(), rng.gen(), rng.gen());
+      let mat: Arc<dyn Material> = if rng.gen::<f64>() < 0.8 {
+        Arc::new(Lambertian::new(albedo))
+      } else {
+        Arc::new(Metal::new(albedo, 0.2))
+      };
+      world.add(Arc::new(Sphere::new(center, 0.1, mat)));
+    }
+  }
+  let primitives = world.objects.len();
+
+  //Create the Camera
+  let mut camera = Camera::new(image_width as f64, image_height as f64);
+  camera.set_samples_per_pixel(4);
+  camera.set_max_depth(Some(5));
+  camera.set_vfov(30.0);
+  camera.set_look_from(Vec3::new(0.0, 6.0, 14.0));
+  camera.set_look_at(Vec3::new(0.0, 0.0, 0.0));
+
+  //Render the linear list, then the BVH, timing each
+  let mut image_buffer = image::ImageBuffer::new(image_width, image_height);
+  let start = Instant::now();
+  camera.render(&mut image_buffer, &world);
+  let list_time = start.elapsed();
+
+  let start = Instant::now();
+  let bvh = BvhNode::new(world);
+  let build_time = start.elapsed();
+
+  let start = Instant::now();
+  camera.render(&mut image_buffer, &bvh);
+  let bvh_time = start.elapsed();
+
+  println!("primitives:      {}", primitives);
+  println!("HittableList:    {:.3?}", list_time);
+  println!("BvhNode build:   {:.3?}", build_time);
+  println!("BvhNode render:  {:.3?}", bvh_time);
+  println!("speedup:         {:.1}x", list_time.as_secs_f64() / bvh_time.as_secs_f64());
+}
@@ -0,0 +1,184 @@
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+///An axis-aligned bounding box, stored as one Interval per axis.
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+impl Aabb {
+    //Constructors
+    pub fn new(x: Interval, y: Interval, z: Interval) -> Self {
+        Aabb { x, y, z }
+    }
+
+    ///Create the box with the two points a and b as its extrema.
+    ///
+    ///The points may be given in any order.
+    pub fn new_from_points(a: Vec3, b: Vec3) -> Self {
+        Aabb {
+            x: Interval::new(a.x.min(b.x), a.x.max(b.x)),
+            y: Interval::new(a.y.min(b.y), a.y.max(b.y)),
+            z: Interval::new(a.z.min(b.z), a.z.max(b.z)),
+        }
+    }
+
+    ///Create the tightest box enclosing both input boxes
+    pub fn new_enclosing(box0: &Aabb, box1: &Aabb) -> Self {
+        Aabb {
+            x: Interval::new_enclosing(&box0.x, &box1.x),
+            y: Interval::new_enclosing(&box0.y, &box1.y),
+            z: Interval::new_enclosing(&box0.z, &box1.z),
+        }
+    }
+
+    //Methods
+    ///Returns a copy of the box with no side narrower than a small delta. 
+    /// 
+    ///Flat objects, such as axis-aligned triangles, would otherwise have a zero 
+    /// width box which rays can never hit.
+    pub fn pad_to_minimums(self: &Self) -> Aabb {
+        let delta = 0.0001;
+        let pad = |interval: Interval| if interval.size() < delta { interval.expand(delta) } else { interval };
+        Aabb::new(pad(self.x), pad(self.y), pad(self.z))
+    }
+
+    ///Returns the interval for axis n: 0 = x, 1 = y, 2 = z
+    pub fn axis_interval(self: &Self, n: usize) -> &Interval {
+        match n {
+            1 => &self.y,
+            2 => &self.z,
+            _ => &self.x,
+        }
+    }
+
+    ///Returns the index of the longest axis of the box
+    pub fn longest_axis(self: &Self) -> usize {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() { 0 } else { 2 }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    ///Returns the point at the center of the box
+    pub fn centroid(self: &Self) -> Vec3 {
+        Vec3::new(
+            (self.x.min + self.x.max) / 2.0,
+            (self.y.min + self.y.max) / 2.0,
+            (self.z.min + self.z.max) / 2.0,
+        )
+    }
+
+    ///Returns the surface area of the box, or 0 for an empty box
+    pub fn surface_area(self: &Self) -> f64 {
+        let (dx, dy, dz) = (self.x.size(), self.y.size(), self.z.size());
+        if dx < 0.0 || dy < 0.0 || dz < 0.0 {
+            0.0
+        } else {
+            2.0 * (dx * dy + dy * dz + dz * dx)
+        }
+    }
+
+    ///Does the ray pass through the box for some t in ray_t?
+    ///
+    ///Uses the slab method: the ray must be inside all three axis slabs at once.
+    pub fn hit(self: &Self, r: &Ray, ray_t: Interval) -> bool {
+        let ray_orig = r.origin();
+        let ray_dir = r.direction();
+        let mut ray_t = ray_t;
+
+        for axis in 0..3 {
+            let ax = self.axis_interval(axis);
+            let adinv = 1.0 / ray_dir[axis];
+
+            let t0 = (ax.min - ray_orig[axis]) * adinv;
+            let t1 = (ax.max - ray_orig[axis]) * adinv;
+
+            //Narrow ray_t to the part of the ray inside this slab
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            if t0 > ray_t.min { ray_t.min = t0; }
+            if t1 < ray_t.max { ray_t.max = t1; }
+
+            if ray_t.max <= ray_t.min {
+                return false;
+            }
+        }
+        true
+    }
+
+    //Associated constants
+    pub const EMPTY: Self = Self {
+        x: Interval::EMPTY,
+        y: Interval::EMPTY,
+        z: Interval::EMPTY,
+    };
+    pub const UNIVERSE: Self = Self {
+        x: Interval::UNIVERSE,
+        y: Interval::UNIVERSE,
+        z: Interval::UNIVERSE,
+    };
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hit_is_true_for_ray_through_box() {
+        //Arrange
+        let bbox = Aabb::new_from_points(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let r = Ray::new(Vec3::new(0.5, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        //Act
+        let result = bbox.hit(&r, Interval::new(0.0, f64::INFINITY));
+
+        //Assert
+        assert!(result);
+    }
+
+    #[test]
+    fn hit_is_false_for_ray_missing_box() {
+        //Arrange
+        let bbox = Aabb::new_from_points(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let r = Ray::new(Vec3::new(2.0, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        //Act
+        let result = bbox.hit(&r, Interval::new(0.0, f64::INFINITY));
+
+        //Assert
+        assert!(!result);
+    }
+
+    #[test]
+    fn hit_is_false_for_box_outside_ray_interval() {
+        //Arrange
+        let bbox = Aabb::new_from_points(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let r = Ray::new(Vec3::new(0.5, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        //Act
+        let result = bbox.hit(&r, Interval::new(0.0, 3.0));
+
+        //Assert
+        assert!(!result);
+    }
+
+    #[test]
+    fn surface_area_of_unit_cube_is_6() {
+        //Arrange
+        let bbox = Aabb::new_from_points(Vec3::new_zeroes(), Vec3::new(1.0, 1.0, 1.0));
+
+        //Act
+        let result = bbox.surface_area();
+
+        //Assert
+        assert_eq!(6.0, result);
+    }
+}
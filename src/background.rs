@@ -0,0 +1,292 @@
+use std::f64::consts::PI;
+use std::path::Path;
+
+use image::{ImageResult, Rgb32FImage};
+use rand::Rng;
+
+use crate::distribution::Distribution2D;
+use crate::random::RenderRng;
+use crate::ray::Ray;
+use crate::vec3::{color::Color, Vec3};
+
+///The light arriving along rays that miss every object in the world.
+///
+///Backgrounds are shared between render threads, so must be Send + Sync.
+pub trait Background: Send + Sync {
+    fn color(self: &Self, r: &Ray) -> Color;
+
+    ///Sample a direction towards the background, favouring its brighter parts. 
+    /// 
+    ///Returns the unit direction and its PDF with respect to solid angle, or 
+    /// None if the background cannot be sampled, e.g. because it is black.
+    fn sample_direction(self: &Self, _rng: &mut RenderRng) -> Option<(Vec3, f64)> {
+        None
+    }
+
+    ///The PDF, with respect to solid angle, of sample_direction returning 
+    /// the given direction.
+    fn pdf_value(self: &Self, _direction: &Vec3) -> f64 {
+        0.0
+    }
+}
+
+///A background of a single color in every direction
+#[derive(Clone, Copy, Debug)]
+pub struct SolidBackground {
+    pub color: Color,
+}
+
+impl SolidBackground {
+    pub fn new(color: Color) -> Self {
+        SolidBackground { color }
+    }
+}
+
+impl Background for SolidBackground {
+    fn color(self: &Self, _r: &Ray) -> Color {
+        self.color
+    }
+}
+
+///A background blending vertically from the bottom color, looking straight
+/// down, to the top color, looking straight up.
+#[derive(Clone, Copy, Debug)]
+pub struct GradientBackground {
+    pub bottom: Color,
+    pub top: Color,
+}
+
+impl GradientBackground {
+    pub fn new(bottom: Color, top: Color) -> Self {
+        GradientBackground { bottom, top }
+    }
+
+    ///The white to blue daylight sky
+    pub fn sky() -> Self {
+        GradientBackground::new(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Background for GradientBackground {
+    fn color(self: &Self, r: &Ray) -> Color {
+        let unit_direction = Vec3::unit_vector(r.direction());
+        let a = 0.5 * (unit_direction.y + 1.0);
+        (1.0 - a) * self.bottom + a * self.top
+    }
+}
+
+///No background: rays that miss everything carry no light, so the scene is
+/// lit only by its emissive materials.
+#[derive(Clone, Copy, Debug)]
+pub struct NoBackground;
+
+impl Background for NoBackground {
+    fn color(self: &Self, _r: &Ray) -> Color {
+        Color::new_zeroes()
+    }
+}
+
+///A background lit by an equirectangular (latitude-longitude) image, such as
+/// a studio HDRI.
+///
+///The image's top row is straight up, its bottom row straight down, and its
+/// columns wrap once around the vertical axis. Each pixel is treated as a 
+/// constant radiance over its patch of directions.
+/// 
+///Directions are importance sampled in proportion to the luminance of the
+/// pixels, so small bright lights such as the sun are found by few rays.
+#[derive(Clone, Debug)]
+pub struct EnvironmentMap {
+    image: Rgb32FImage,
+    rotation: f64,      // rotation about the vertical axis in radians
+    intensity: f64,     // scale applied to the image's values
+    distribution: Distribution2D,   // pixel sampling distribution
+}
+
+impl EnvironmentMap {
+    //Constructors
+    ///Create an environment from an image, rotated anticlockwise (seen from above) 
+    /// about the vertical axis by rotation degrees, with its values scaled by intensity.
+    pub fn new(image: Rgb32FImage, rotation: f64, intensity: f64) -> Self {
+        //Rows near the poles cover less solid angle than those at the equator, 
+        // so each pixel's luminance is weighted by the sine of its latitude
+        let (width, height) = image.dimensions();
+        let rows = (0..height).map(|row| {
+            let sin_theta = (PI * (row as f64 + 0.5) / height as f64).sin();
+            (0..width).map(|column| {
+                let pixel = image.get_pixel(column, row);
+                let color = Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64);
+                color.luminance().max(0.0) * sin_theta
+            }).collect()
+        }).collect();
+        let distribution = Distribution2D::new(rows);
+
+        EnvironmentMap { image, rotation: rotation.to_radians(), intensity, distribution }
+    }
+
+    ///Load the environment from an image file. 
+    /// 
+    ///Radiance .hdr and OpenEXR .exr files keep their full range of values; 
+    /// any other image format the image crate reads can also be used.
+    pub fn load(path: impl AsRef<Path>, rotation: f64, intensity: f64) -> ImageResult<Self> {
+        let image = image::open(path)?.into_rgb32f();
+        Ok(EnvironmentMap::new(image, rotation, intensity))
+    }
+
+    //Methods
+    ///Returns the image coordinates (u, v), both in [0, 1), seen looking along direction
+    fn uv_for(self: &Self, direction: &Vec3) -> (f64, f64) {
+        let d = Vec3::unit_vector(direction);
+        //Latitude, 0 looking straight up to PI looking straight down
+        let theta = d.y.clamp(-1.0, 1.0).acos();
+        //Longitude, anticlockwise about the vertical axis from +x, less the map's rotation
+        let phi = ((-d.z).atan2(d.x) - self.rotation).rem_euclid(2.0 * PI);
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    ///The inverse of uv_for: returns the unit direction looking at (u, v)
+    fn direction_for(self: &Self, u: f64, v: f64) -> Vec3 {
+        let theta = v * PI;
+        let phi = u * 2.0 * PI + self.rotation;
+        Vec3::new(theta.sin() * phi.cos(), theta.cos(), -theta.sin() * phi.sin())
+    }
+
+    ///Returns the (column, row) of the pixel seen looking along direction
+    fn pixel_for(self: &Self, direction: &Vec3) -> (u32, u32) {
+        let (u, v) = self.uv_for(direction);
+        let (width, height) = self.image.dimensions();
+        let column = (u * width as f64) as u32;
+        let row = (v * height as f64) as u32;
+        (column.min(width - 1), row.min(height - 1))
+    }
+
+    //Associated functions
+    ///Convert a PDF over the image's unit square to one over directions.
+    /// The image spans 2PI x PI radians, and a patch at latitude theta 
+    /// covers sin(theta) times the solid angle of one on the equator.
+    fn solid_angle_pdf(pdf_uv: f64, v: f64) -> f64 {
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            0.0
+        } else {
+            pdf_uv / (2.0 * PI * PI * sin_theta)
+        }
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn color(self: &Self, r: &Ray) -> Color {
+        let (column, row) = self.pixel_for(r.direction());
+        let pixel = self.image.get_pixel(column, row);
+        self.intensity * Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64)
+    }
+
+    fn sample_direction(self: &Self, rng: &mut RenderRng) -> Option<(Vec3, f64)> {
+        let ((u, v), pdf_uv) = self.distribution.sample_continuous(rng.gen(), rng.gen());
+        let direction = self.direction_for(u, v);
+        let pdf = EnvironmentMap::solid_angle_pdf(pdf_uv, v);
+        if pdf > 0.0 { Some((direction, pdf)) } else { None }
+    }
+
+    fn pdf_value(self: &Self, direction: &Vec3) -> f64 {
+        let (u, v) = self.uv_for(direction);
+        EnvironmentMap::solid_angle_pdf(self.distribution.pdf(u, v), v)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    //A 4 x 2 environment: each pixel's red channel is its column and its
+    // green channel is its row
+    fn test_map(rotation: f64) -> EnvironmentMap {
+        let image = Rgb32FImage::from_fn(4, 2, |x, y| image::Rgb([x as f32, y as f32, 1.0]));
+        EnvironmentMap::new(image, rotation, 2.0)
+    }
+
+    fn look(map: &EnvironmentMap, direction: Vec3) -> Color {
+        map.color(&Ray::new(Vec3::new_zeroes(), direction))
+    }
+
+    #[test]
+    fn environment_map_looks_up_top_row_above_and_bottom_row_below() {
+        //Arrange
+        let map = test_map(0.0);
+
+        //Act
+        let above = look(&map, Vec3::new(0.1, 1.0, 0.1));
+        let below = look(&map, Vec3::new(0.1, -1.0, 0.1));
+
+        //Assert
+        assert_eq!(0.0, above.g());
+        assert_eq!(2.0, below.g());
+    }
+
+    #[test]
+    fn environment_map_loads_radiance_hdr_file() {
+        //Arrange
+        let path = std::env::temp_dir().join("rtiow_environment_map.hdr");
+        let image = Rgb32FImage::from_pixel(8, 4, image::Rgb([4.0, 2.0, 0.5]));
+        image.save(&path).unwrap();
+
+        //Act
+        let map = EnvironmentMap::load(&path, 0.0, 1.0).unwrap();
+        let result = look(&map, Vec3::new(0.0, 1.0, 0.0));
+
+        //Assert
+        assert_eq!((4.0, 2.0, 0.5), (result.r(), result.g(), result.b()));
+    }
+
+    #[test]
+    fn environment_map_samples_bright_pixels_with_matching_pdf() {
+        //Arrange
+        //A dark environment with one bright pixel
+        let image = Rgb32FImage::from_fn(8, 4, |x, y| {
+            if (x, y) == (5, 1) { image::Rgb([100.0, 100.0, 100.0]) } else { image::Rgb([0.01, 0.01, 0.01]) }
+        });
+        let map = EnvironmentMap::new(image, 30.0, 1.0);
+        let mut rng = crate::random::new_rng(3);
+        let mut bright = 0;
+
+        for _ in 0..1000 {
+            //Act
+            let (direction, pdf) = map.sample_direction(&mut rng).unwrap();
+
+            //Assert
+            let expected = map.pdf_value(&direction);
+            assert!((expected - pdf).abs() <= 1e-9 * pdf, "pdf {} != {}", pdf, expected);
+            if map.pixel_for(&direction) == (5, 1) {
+                bright += 1;
+            }
+        }
+        assert!(bright > 950, "only {} samples were of the bright pixel", bright);
+    }
+
+    #[test]
+    fn environment_map_scales_by_intensity() {
+        //Arrange
+        let map = test_map(0.0);
+
+        //Act
+        let result = look(&map, Vec3::new(1.0, 0.5, 0.1));
+
+        //Assert
+        assert_eq!(2.0, result.b());
+    }
+
+    #[test]
+    fn environment_map_rotation_turns_the_image() {
+        //Arrange
+        let map = test_map(0.0);
+        let rotated = test_map(90.0);
+
+        //Act
+        let expected = look(&map, Vec3::new(1.0, 0.2, 0.1));
+        let result = look(&rotated, Vec3::new(0.1, 0.2, -1.0));
+
+        //Assert
+        assert_eq!(expected.r(), result.r());
+    }
+}
@@ -1,13 +1,38 @@
+use std::sync::Arc;
+
 use image::{ImageBuffer, Rgb};
-use rand::{thread_rng, Rng};
+use rand::Rng;
+use rayon::{prelude::*, ThreadPoolBuilder};
 
-use crate::hittable::{Hittable, HittableList};
-use crate::interval::Interval;
+use crate::background::Background;
+use crate::hittable::Hittable;
+use crate::integrator::{Integrator, PathTracer};
+use crate::random::{pixel_rng, RenderRng};
 use crate::ray::Ray;
 use crate::vec3::{color::Color, Vec3};
 
 pub struct Camera {
+    //Image data
+    image_width: f64,
+    image_height: f64,
+    //View data
+    vfov: f64,                  // vertical view angle (field of view) in degrees; default to 90
+    lookfrom: Vec3,             // point the camera is looking from; default to the origin
+    lookat: Vec3,               // point the camera is looking at; default to (0,0,-1)
+    vup: Vec3,                  // camera-relative "up" direction; default to (0,1,0)
     center: Vec3,
+    //Defocus (depth of field) data
+    defocus_angle: f64,         // variation angle of rays through each pixel in degrees; default to 0 (no blur)
+    focus_dist: f64,            // distance from lookfrom to the plane of perfect focus; default to 10
+    defocus_disk_u: Vec3,       // defocus disk horizontal radius
+    defocus_disk_v: Vec3,       // defocus disk vertical radius
+    //Motion blur data
+    shutter_open: f64,          // time the shutter opens; default to 0
+    shutter_close: f64,         // time the shutter closes; default to 1
+    //Camera frame basis vectors 
+    u: Vec3,                    // unit vector pointing to the camera's right
+    v: Vec3,                    // unit vector pointing up from the camera
+    w: Vec3,                    // unit vector pointing opposite the view direction
     //Viewport data 
     pixel00_loc: Vec3,
     pixel_delta_u: Vec3,
@@ -15,80 +40,153 @@ pub struct Camera {
     //Sampling data
     samples_per_pixel: u32,     // default to 10
     pixel_samples_scale: f64,   // = 1/samples_per_pixel 
-    max_depth: u32,             // maximum number of ray bounces; default to 10
+    //Rendering data
+    threads: usize,             // number of render threads; default to 0 (one per CPU core)
+    seed: u64,                  // seed for all random sampling; default to 0
+    path_tracer: PathTracer,    // finds the colour along each ray; default to no maximum depth, the sky and no lights
+    integrator: Option<Arc<dyn Integrator>>,    // used in place of the path tracer; default to none
 }
 
 impl Camera { 
     //Constructors 
+    ///Create a camera at the origin looking down the -z axis with a 90 degree
+    /// vertical field of view. Use the setters to reposition it.
     pub fn new(image_width: f64, image_height: f64) -> Self {
+        let mut camera = Camera { 
+            image_width, 
+            image_height,
+            vfov: 90.0,
+            lookfrom: Vec3::new_zeroes(),
+            lookat: Vec3::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            center: Vec3::new_zeroes(), 
+            defocus_angle: 0.0,
+            focus_dist: 10.0,
+            defocus_disk_u: Vec3::new_zeroes(),
+            defocus_disk_v: Vec3::new_zeroes(),
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            u: Vec3::new_zeroes(),
+            v: Vec3::new_zeroes(),
+            w: Vec3::new_zeroes(),
+            pixel00_loc: Vec3::new_zeroes(), 
+            pixel_delta_u: Vec3::new_zeroes(), 
+            pixel_delta_v: Vec3::new_zeroes(), 
+            samples_per_pixel: 10,
+            pixel_samples_scale: 0.1, 
+            threads: 0,
+            seed: 0,
+            path_tracer: PathTracer::new(),
+            integrator: None,
+        };
+        camera.initialize();
+        camera
+    }
+
+    ///Recalculate the camera frame and viewport from the view settings.
+    /// 
+    ///Must be called whenever the position, orientation, field of view or focus changes.
+    fn initialize(self: &mut Self) {
         //Configure the camera
-        let center = Vec3::new_zeroes();
+        self.center = self.lookfrom;
 
         //Configure the viewport, using the actual aspect ratio for the image
-        let focal_length = 1.0; 
-        let viewport_height = 2.0;
+        let theta = self.vfov.to_radians();
+        let h = (theta / 2.0).tan();
+        let viewport_height = 2.0 * h * self.focus_dist;
         let viewport_width 
-          = viewport_height * (image_width / image_height);
+          = viewport_height * (self.image_width / self.image_height);
+
+        //Calculate the u,v,w unit basis vectors for the camera coordinate frame
+        self.w = Vec3::unit_vector(&(self.lookfrom - self.lookat));
+        self.u = Vec3::unit_vector(&Vec3::cross(&self.vup, &self.w));
+        self.v = Vec3::cross(&self.w, &self.u);
       
-        let viewport_u  //Vector along viewport top edge going r-to-l
-          = Vec3::new(viewport_width, 0.0, 0.0);
+        let viewport_u  //Vector along viewport top edge going l-to-r
+          = viewport_width * self.u;
         let viewport_v  //Vector along viewport left edge going t-to-b 
-          = Vec3::new(0.0, -viewport_height, 0.0);
-        let pixel_delta_u  //Horizontal vector between two pixels
-          = viewport_u / image_width;
-        let pixel_delta_v  //Vertical vector between two pixels
-          = viewport_v / image_height;
+          = viewport_height * -self.v;
+        self.pixel_delta_u  //Horizontal vector between two pixels
+          = viewport_u / self.image_width;
+        self.pixel_delta_v  //Vertical vector between two pixels
+          = viewport_v / self.image_height;
 
         //Calculate location of upper left pixel, pixel00, relative to the camera center
         let viewport_upper_left 
-        = center 
-            - Vec3::new(0.0, 0.0, focal_length) 
+        = self.center 
+            - (self.focus_dist * self.w) 
             - viewport_u / 2.0 
             - viewport_v / 2.0;
-        let pixel00_loc   //Pixels are inset by half the pixel-to-pixel distance 
-        = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v); 
-
-        Camera { 
-            center: center, 
-            pixel00_loc: pixel00_loc, 
-            pixel_delta_u: pixel_delta_u, 
-            pixel_delta_v: pixel_delta_v, 
-            samples_per_pixel: 10,
-            pixel_samples_scale: 0.1, 
-            max_depth: 10
-        }
+        self.pixel00_loc   //Pixels are inset by half the pixel-to-pixel distance 
+        = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v); 
+
+        //Calculate the camera defocus disk basis vectors
+        let defocus_radius = self.focus_dist * (self.defocus_angle / 2.0).to_radians().tan();
+        self.defocus_disk_u = defocus_radius * self.u;
+        self.defocus_disk_v = defocus_radius * self.v;
     }
 
     //Methods
-    pub fn render(self: &mut Self, img_buf: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, world: &HittableList) {
+    ///Render the world onto the ImageBuffer, spreading the pixels across 
+    /// the configured number of threads.
+    pub fn render(self: &Self, img_buf: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, world: &dyn Hittable) {
+        let integrator = self.integrator.as_deref().unwrap_or(&self.path_tracer);
+
+        //Each pixel is independent of every other, so the pixels can be 
+        // rendered in any order, by any thread.
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .build()
+            .expect("failed to create the render thread pool");
+
         //  Update the Pixels in the ImageBuffer with the RGB values we want    
-        for (u, v, pixel) in img_buf.enumerate_pixels_mut() {
-            // eprint!("\rScanlines remaining: {}   ", height - y);
-
-            //Calculate the pixel colour by random sampling in a square 
-            //  around the pixel's viewport location and averaging the samples
-            let mut pixel_color = Color::new_zeroes();
-            for _sample in 0..self.samples_per_pixel {
-                let r = self.get_ray(u as f64, v as f64);
-                //All colour calculations are done using f64 values in [0.0 .. 1.0]
-                pixel_color  = pixel_color + Camera::ray_color(&r, self.max_depth, world); 
-            }
-            pixel_color = self.pixel_samples_scale * pixel_color;
-
-            //Now we store the colour in the image buffer
-            *pixel = Rgb(pixel_color.output_color());
-        }
+        pool.install(|| {
+            img_buf.par_enumerate_pixels_mut().for_each(|(u, v, pixel)| {
+                //Calculate the pixel colour by random sampling in a square 
+                //  around the pixel's viewport location and averaging the samples
+                let mut pixel_color = Color::new_zeroes();
+                for sample in 0..self.samples_per_pixel {
+                    //Each sample has its own generator so the image is the same
+                    // however the pixels are shared out between threads
+                    let mut rng = pixel_rng(self.seed, u, v, sample);
+                    let r = self.get_ray(u as f64, v as f64, &mut rng);
+                    //All colour calculations are done using f64 values in [0.0 .. 1.0]
+                    pixel_color  = pixel_color + integrator.ray_color(&r, world, &mut rng); 
+                }
+                pixel_color = self.pixel_samples_scale * pixel_color;
+
+                //Now we store the colour in the image buffer
+                *pixel = Rgb(pixel_color.output_color());
+            });
+        });
     }
      
-    ///Construct a ray passing through a randomly chosen point in the 
-    /// unit square around the given (u,v) location on the camera's viewport.
-    fn get_ray(self: &Self, u: f64, v: f64) ->Ray {
-        let offset = Camera::sample_square();
+    ///Construct a ray originating from the defocus disk and passing through  
+    /// a randomly chosen point in the unit square around the given (u,v) 
+    /// location on the camera's viewport.
+    fn get_ray(self: &Self, u: f64, v: f64, rng: &mut RenderRng) ->Ray {
+        let offset = Camera::sample_square(rng);
         let pixel_sample = self.pixel00_loc
           + ((u + offset.x) * self.pixel_delta_u)
           + ((v + offset.y) * self.pixel_delta_v);
-        let ray_direction = pixel_sample - self.center; 
-        Ray::new(self.center, ray_direction) 
+        let ray_origin = if self.defocus_angle <= 0.0 { 
+            self.center 
+        } else { 
+            self.defocus_disk_sample(rng) 
+        };
+        let ray_direction = pixel_sample - ray_origin; 
+        let ray_time = if self.shutter_close > self.shutter_open {
+            rng.gen_range(self.shutter_open..self.shutter_close)
+        } else {
+            self.shutter_open
+        };
+        Ray::new_with_time(ray_origin, ray_direction, ray_time) 
+    }
+
+    ///Returns a random point in the camera defocus disk
+    fn defocus_disk_sample(self: &Self, rng: &mut RenderRng) -> Vec3 {
+        let p = Vec3::random_in_unit_disk(rng);
+        self.center + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v)
     }
 
     pub fn set_samples_per_pixel(self: &mut Self, rate: u32) {
@@ -96,45 +194,191 @@ impl Camera {
         self.pixel_samples_scale = 1f64 / rate as f64;
     }
 
-    pub fn set_max_depth(self: &mut Self, depth: u32) {
-        self.max_depth = depth;
+    ///Set the maximum number of ray bounces of the path tracer, see 
+    /// PathTracer::set_max_depth
+    pub fn set_max_depth(self: &mut Self, depth: Option<u32>) {
+        self.path_tracer.set_max_depth(depth);
     }
 
-    //Associated functions
-    fn ray_color(r: &Ray, depth:u32, world: &HittableList) -> Color {
-        if depth <=0 {
-            return Color::new_zeroes();
-        }
-        
-        let hit_test = world.hit(r, Interval::new(0.001, f64::INFINITY));
-      
-        match hit_test {
-            Some(hit_record) => {
-                //Part of a hittable, so compute colour for a mid-grey diffuse material
-                if let Some((attenuation, scattered)) = hit_record.mat.scatter( r, &hit_record) {
-                    attenuation * Camera::ray_color(&scattered, depth - 1, world)
-                } else {
-                    //No ray returned from scatter, probably because it was 
-                    // absorbed by the material, so no more ray bounces   
-                    Color::new_zeroes()
-                }
-            }
-            None => {
-                //Part of the background, so compute blue gradient
-                let unit_direction = Vec3::unit_vector(r.direction());
-                let a = 0.5 * (unit_direction.y + 1.0); 
-                (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)   
-            }
-        }
-    } 
+    ///Set the number of bounces before the path tracer's Russian roulette may 
+    /// end a path, see PathTracer::set_roulette_min_depth
+    pub fn set_roulette_min_depth(self: &mut Self, depth: u32) {
+        self.path_tracer.set_roulette_min_depth(depth);
+    }
+
+    ///Set the background seen by rays that miss every object
+    pub fn set_background(self: &mut Self, background: Arc<dyn Background>) {
+        self.path_tracer.set_background(background);
+    }
+
+    ///Set the lights the path tracer samples directly, see PathTracer::set_lights
+    pub fn set_lights(self: &mut Self, lights: Arc<dyn Hittable>) {
+        self.path_tracer.set_lights(lights);
+    }
+
+    ///Set an integrator to find the colour along each ray in place of the 
+    /// path tracer, e.g. to show what the rays hit rather than the light, or 
+    /// None to go back to the path tracer
+    pub fn set_integrator(self: &mut Self, integrator: Option<Arc<dyn Integrator>>) {
+        self.integrator = integrator;
+    }
+
+    ///Set the size, in pixels, of the image the camera renders
+    pub fn set_image_size(self: &mut Self, image_width: f64, image_height: f64) {
+        self.image_width = image_width;
+        self.image_height = image_height;
+        self.initialize();
+    }
+
+    ///Set the number of threads used to render. 
+    /// 
+    ///A value of 0 uses one thread per CPU core.
+    pub fn set_threads(self: &mut Self, threads: usize) {
+        self.threads = threads;
+    }
+
+    ///Set the seed used for all random sampling. 
+    /// 
+    ///Renders of the same world with the same seed are identical.
+    pub fn set_seed(self: &mut Self, seed: u64) {
+        self.seed = seed;
+    }
+
+    ///Set the vertical field of view, in degrees
+    pub fn set_vfov(self: &mut Self, degrees: f64) {
+        self.vfov = degrees;
+        self.initialize();
+    }
 
+    ///Set the point the camera is looking from
+    pub fn set_look_from(self: &mut Self, lookfrom: Vec3) {
+        self.lookfrom = lookfrom;
+        self.initialize();
+    }
+
+    ///Set the point the camera is looking at
+    pub fn set_look_at(self: &mut Self, lookat: Vec3) {
+        self.lookat = lookat;
+        self.initialize();
+    }
+
+    ///Set the aperture as the angle, in degrees, of the cone with its apex at 
+    /// the focus plane and its base at the camera's defocus disk. 
+    /// 
+    ///An angle of 0 gives a pinhole camera with everything in focus.
+    pub fn set_defocus_angle(self: &mut Self, degrees: f64) {
+        self.defocus_angle = degrees;
+        self.initialize();
+    }
+
+    ///Set the distance from the look-from point to the plane of perfect focus
+    pub fn set_focus_dist(self: &mut Self, focus_dist: f64) {
+        self.focus_dist = focus_dist;
+        self.initialize();
+    }
+
+    ///Set the interval during which the shutter is open. Each ray is cast at
+    /// a random time in the interval, so objects moving during it are blurred.
+    /// 
+    ///Moving objects are animated from time 0 to 1. An empty interval, 
+    /// open == close, freezes the world at that moment.
+    pub fn set_shutter(self: &mut Self, open: f64, close: f64) {
+        self.shutter_open = open;
+        self.shutter_close = close;
+    }
+
+    ///Set the camera-relative "up" direction. 
+    /// 
+    ///This need not be perpendicular to the view direction, but must not be parallel to it.
+    pub fn set_vup(self: &mut Self, vup: Vec3) {
+        self.vup = vup;
+        self.initialize();
+    }
+
+    //Associated functions
     ///Returns a Vec3 through a random point in the unit square which lies in the xy-plane 
     /// and is centered on the origin.
     /// 
     /// Excludes points on the right and bottom edges of the square.
-    fn sample_square() -> Vec3 {
-        let mut rng = thread_rng();
+    fn sample_square(rng: &mut RenderRng) -> Vec3 {
         Vec3::new(rng.gen_range(-0.5..0.5), rng.gen_range(-0.5..0.5), 0f64)
     }
+
     
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hittable::{HittableList, Sphere};
+    use crate::material::{Dielectric, Lambertian, Material};
+    use crate::ray::Ray;
+
+    //Sees the same colour along every ray
+    struct ConstantIntegrator {
+        color: Color,
+    }
+
+    impl Integrator for ConstantIntegrator {
+        fn ray_color(self: &Self, _r: &Ray, _world: &dyn Hittable, _rng: &mut RenderRng) -> Color {
+            self.color
+        }
+    }
+
+    //A small world with both diffuse and randomly reflecting materials
+    fn test_world() -> HittableList {
+        let ground: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.0)));
+        let glass: Arc<dyn Material> = Arc::new(Dielectric::new(1.5));
+        let mut world = HittableList::new_empty();
+        world.add(Arc::new(Sphere::new(Vec3::new(0.0, -100.5, -1.0), 100.0, ground)));
+        world.add(Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, glass)));
+        world
+    }
+
+    fn render_with(threads: usize, seed: u64) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+        let mut img_buf = ImageBuffer::new(16, 9);
+        let mut camera = Camera::new(16.0, 9.0);
+        camera.set_samples_per_pixel(4);
+        camera.set_threads(threads);
+        camera.set_seed(seed);
+        camera.render(&mut img_buf, &test_world());
+        img_buf
+    }
+
+    #[test]
+    fn render_with_same_seed_is_identical_for_any_thread_count() {
+        //Arrange 
+        let expected = render_with(1, 42);
+
+        //Act
+        let result = render_with(4, 42);
+
+        //Assert
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn render_uses_the_integrator_set() {
+        //Arrange 
+        let color = Color::new(0.25, 0.5, 1.0);
+        let mut img_buf = ImageBuffer::new(16, 9);
+        let mut camera = Camera::new(16.0, 9.0);
+        camera.set_integrator(Some(Arc::new(ConstantIntegrator { color })));
+
+        //Act
+        camera.render(&mut img_buf, &test_world());
+
+        //Assert
+        assert!(img_buf.pixels().all(|pixel| *pixel == Rgb(color.output_color())));
+    }
+
+    #[test]
+    fn render_with_different_seeds_differs() {
+        //Act
+        let first = render_with(1, 1);
+        let second = render_with(1, 2);
+
+        //Assert
+        assert_ne!(first, second);
+    }
+}
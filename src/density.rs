@@ -0,0 +1,147 @@
+use crate::aabb::Aabb;
+use crate::texture::perlin::Perlin;
+use crate::vec3::Vec3;
+
+///A density that varies through space, filling a heterogeneous medium.
+///
+///Density fields are shared between render threads, so must be Send + Sync.
+pub trait DensityField: Send + Sync {
+    ///The density at p, never negative
+    fn density(self: &Self, p: &Vec3) -> f64;
+
+    ///An upper bound on the density anywhere
+    fn max_density(self: &Self) -> f64;
+}
+
+///Densities sampled on a regular 3D grid filling bounds, interpolated
+/// trilinearly between the centers of the cells. Outside bounds the
+/// density is 0.
+#[derive(Clone, Debug)]
+pub struct GridDensity {
+    bounds: Aabb,
+    resolution: [usize; 3],
+    values: Vec<f64>,   // x varies fastest, then y, then z
+    max: f64,
+}
+
+impl GridDensity {
+    //Constructors
+    ///Create the grid from resolution[0] x resolution[1] x resolution[2]
+    /// values, with x varying fastest, then y, then z.
+    ///
+    /// # Panics
+    /// If a resolution is 0, the number of values does not match it, or a
+    /// value is negative.
+    pub fn new(bounds: Aabb, resolution: [usize; 3], values: Vec<f64>) -> Self {
+        assert!(resolution.iter().all(|&n| n > 0), "grid resolution must be at least 1");
+        assert_eq!(resolution.iter().product::<usize>(), values.len(), "grid needs one value per cell");
+        assert!(values.iter().all(|&value| value >= 0.0), "densities must not be negative");
+
+        let max = values.iter().cloned().fold(0.0, f64::max);
+        GridDensity { bounds, resolution, values, max }
+    }
+
+    //Methods
+    fn value(self: &Self, x: usize, y: usize, z: usize) -> f64 {
+        let [nx, ny, _] = self.resolution;
+        self.values[x + nx * (y + ny * z)]
+    }
+}
+
+impl DensityField for GridDensity {
+    fn density(self: &Self, p: &Vec3) -> f64 {
+        //Find p's continuous coordinates in the grid, measured from the
+        // centre of the first cell, and the lower cell and weight per axis
+        let mut lower = [0; 3];
+        let mut weight = [0.0; 3];
+        for axis in 0..3 {
+            let interval = self.bounds.axis_interval(axis);
+            if !interval.contains(p[axis]) {
+                return 0.0;
+            }
+            let n = self.resolution[axis];
+            let g = ((p[axis] - interval.min) / interval.size() * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            lower[axis] = (g.floor() as usize).min(n.saturating_sub(2));
+            weight[axis] = g - lower[axis] as f64;
+        }
+
+        let mut accum = 0.0;
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let mut corner_weight = 1.0;
+            let mut index = [0; 3];
+            for axis in 0..3 {
+                index[axis] = (lower[axis] + offset[axis]).min(self.resolution[axis] - 1);
+                corner_weight *= if offset[axis] == 1 { weight[axis] } else { 1.0 - weight[axis] };
+            }
+            accum += corner_weight * self.value(index[0], index[1], index[2]);
+        }
+        accum
+    }
+
+    fn max_density(self: &Self) -> f64 {
+        self.max
+    }
+}
+
+///Billowing Perlin turbulence, like smoke or cloud: depth octaves of noise
+/// with features roughly 1 / scale units across, scaled so the density
+/// runs from 0 to max.
+#[derive(Clone, Debug)]
+pub struct NoiseDensity {
+    noise: Perlin,
+    scale: f64,
+    depth: u32,
+    max: f64,
+}
+
+impl NoiseDensity {
+    //Constructors
+    ///Create the turbulence from the Perlin noise of the given seed.
+    ///
+    /// # Panics
+    /// If scale is not a finite number greater than 0, or max is negative or
+    /// not finite.
+    pub fn new(seed: u64, scale: f64, depth: u32, max: f64) -> Self {
+        assert!(scale.is_finite() && scale > 0.0, "scale must be greater than 0");
+        assert!(max.is_finite() && max >= 0.0, "max density must not be negative");
+        NoiseDensity { noise: Perlin::new(seed), scale, depth, max }
+    }
+}
+
+impl DensityField for NoiseDensity {
+    fn density(self: &Self, p: &Vec3) -> f64 {
+        self.max * self.noise.turb(&(self.scale * *p), self.depth).min(1.0)
+    }
+
+    fn max_density(self: &Self) -> f64 {
+        self.max
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grid_density_interpolates_between_cell_centres() {
+        //Arrange
+        //Two cells along x, 0 then 2, filling the unit cube
+        let bounds = Aabb::new_from_points(Vec3::new_zeroes(), Vec3::new(1.0, 1.0, 1.0));
+        let grid = GridDensity::new(bounds, [2, 1, 1], vec![0.0, 2.0]);
+
+        //Act
+        let first_centre = grid.density(&Vec3::new(0.25, 0.5, 0.5));
+        let between = grid.density(&Vec3::new(0.5, 0.5, 0.5));
+        let edge = grid.density(&Vec3::new(0.99, 0.1, 0.9));
+        let outside = grid.density(&Vec3::new(1.5, 0.5, 0.5));
+
+        //Assert
+        assert_eq!(0.0, first_centre);
+        assert!((between - 1.0).abs() < 1e-12);
+        assert_eq!(2.0, edge);
+        assert_eq!(0.0, outside);
+        assert_eq!(2.0, grid.max_density());
+    }
+}
@@ -0,0 +1,171 @@
+///A piecewise-constant 1D probability distribution over [0, 1), built from
+/// n non-negative function values, one per equal width bucket.
+///
+///Samples are drawn by inverting the cumulative distribution function (CDF),
+/// so buckets with larger values are chosen proportionally more often.
+#[derive(Clone, Debug)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,      // n + 1 values running from 0 to 1
+    func_int: f64,      // integral of func over [0, 1)
+}
+
+impl Distribution1D {
+    //Constructors
+    ///Create the distribution. If every value is 0 it is uniform.
+    ///
+    /// # Panics
+    /// If func is empty.
+    pub fn new(func: Vec<f64>) -> Self {
+        assert!(!func.is_empty(), "a distribution needs at least one value");
+        let n = func.len();
+
+        //Integrate the function, then normalise the running total into the CDF
+        let mut cdf = vec![0.0; n + 1];
+        for i in 1..=n {
+            cdf[i] = cdf[i - 1] + func[i - 1] / n as f64;
+        }
+        let func_int = cdf[n];
+        for (i, value) in cdf.iter_mut().enumerate() {
+            *value = if func_int > 0.0 { *value / func_int } else { i as f64 / n as f64 };
+        }
+
+        Distribution1D { func, cdf, func_int }
+    }
+
+    //Methods
+    pub fn count(self: &Self) -> usize {
+        self.func.len()
+    }
+
+    ///The integral of the function over [0, 1)
+    pub fn integral(self: &Self) -> f64 {
+        self.func_int
+    }
+
+    ///Map a uniform random number u in [0, 1) to a sample x in [0, 1).
+    ///
+    ///Returns x, the PDF at x, and the index of the bucket containing x.
+    pub fn sample_continuous(self: &Self, u: f64) -> (f64, f64, usize) {
+        //Find the last bucket whose CDF value is <= u
+        let offset = self.cdf.partition_point(|&c| c <= u).clamp(1, self.count()) - 1;
+
+        //Find how far through the bucket u lies
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 { (u - self.cdf[offset]) / width } else { 0.0 };
+
+        let x = (offset as f64 + du) / self.count() as f64;
+        (x, self.pdf_at(offset), offset)
+    }
+
+    ///The PDF of the distribution for samples in the given bucket
+    pub fn pdf_at(self: &Self, offset: usize) -> f64 {
+        if self.func_int > 0.0 { self.func[offset] / self.func_int } else { 1.0 }
+    }
+}
+
+///A piecewise-constant 2D probability distribution over [0, 1) x [0, 1),
+/// built from a grid of non-negative function values.
+///
+///A row is sampled from the marginal distribution of the row totals, then a
+/// column from that row's conditional distribution.
+#[derive(Clone, Debug)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    //Constructors
+    ///Create the distribution from the function's values, given row by row.
+    ///
+    /// # Panics
+    /// If there are no rows or columns, or the rows differ in length.
+    pub fn new(rows: Vec<Vec<f64>>) -> Self {
+        let columns = rows.first().map_or(0, Vec::len);
+        assert!(rows.iter().all(|row| row.len() == columns), "every row needs the same number of columns");
+
+        let conditional: Vec<Distribution1D> = rows.into_iter().map(Distribution1D::new).collect();
+        let marginal = Distribution1D::new(conditional.iter().map(Distribution1D::integral).collect());
+        Distribution2D { conditional, marginal }
+    }
+
+    //Methods
+    ///Map two uniform random numbers in [0, 1) to a sample (u, v), where v
+    /// selects the row and u the column. Returns the sample and its PDF.
+    pub fn sample_continuous(self: &Self, u0: f64, u1: f64) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u1);
+        let (u, pdf_u, _) = self.conditional[row].sample_continuous(u0);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    ///The PDF of the distribution at (u, v)
+    pub fn pdf(self: &Self, u: f64, v: f64) -> f64 {
+        let row = ((v * self.marginal.count() as f64) as usize).min(self.marginal.count() - 1);
+        let conditional = &self.conditional[row];
+        let column = ((u * conditional.count() as f64) as usize).min(conditional.count() - 1);
+
+        if self.marginal.integral() > 0.0 {
+            conditional.func[column] / self.marginal.integral()
+        } else {
+            1.0
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sample_continuous_never_picks_zero_buckets() {
+        //Arrange
+        let distribution = Distribution1D::new(vec![0.0, 1.0, 3.0, 0.0]);
+
+        for i in 0..100 {
+            //Act
+            let (x, pdf, offset) = distribution.sample_continuous(i as f64 / 100.0);
+
+            //Assert
+            assert!(offset == 1 || offset == 2, "offset was {}", offset);
+            assert!((0.25..0.75).contains(&x), "x was {}", x);
+            assert_eq!(distribution.pdf_at(offset), pdf);
+        }
+    }
+
+    #[test]
+    fn sample_continuous_picks_buckets_in_proportion_to_values() {
+        //Arrange
+        let distribution = Distribution1D::new(vec![1.0, 3.0]);
+
+        //Act
+        let (_, _, below) = distribution.sample_continuous(0.24);
+        let (_, _, above) = distribution.sample_continuous(0.26);
+
+        //Assert
+        assert_eq!((0, 1), (below, above));
+    }
+
+    #[test]
+    fn distribution_2d_pdf_integrates_to_one() {
+        //Arrange
+        let distribution = Distribution2D::new(vec![
+            vec![0.0, 1.0, 2.0],
+            vec![5.0, 0.5, 0.0],
+        ]);
+
+        //Act
+        //Each cell covers 1/6 of the unit square
+        let mut integral = 0.0;
+        for row in 0..2 {
+            for column in 0..3 {
+                let (u, v) = ((column as f64 + 0.5) / 3.0, (row as f64 + 0.5) / 2.0);
+                integral += distribution.pdf(u, v) / 6.0;
+            }
+        }
+
+        //Assert
+        assert!((integral - 1.0).abs() < 1e-12);
+    }
+}
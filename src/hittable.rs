@@ -1,23 +1,42 @@
+pub mod bvh;
+pub mod instance;
+pub mod medium;
+pub mod planar;
+pub mod triangle;
+
+use std::f64::consts::PI;
 use std::vec::Vec;
-use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rand::Rng;
 
+use crate::aabb::Aabb;
 use crate::interval::Interval;
 use crate::vec3::Vec3;
+use crate::random::RenderRng;
 use crate::ray::Ray;
 use crate::material::Material;
+use crate::onb::Onb;
+use crate::vec3::color::Color;
 
 #[derive(Clone, Debug)]
 pub struct HitRecord {
     pub p: Vec3,
     pub normal: Vec3,
-    pub mat: Rc<dyn Material>,
+    pub mat: Arc<dyn Material>,
     pub t: f64,
     pub front_face: bool,
+    pub u: f64,                     // surface (texture) coordinates of the hit point
+    pub v: f64,
+    pub barycentric: (f64, f64),    // weights of a triangle's second and third vertices
+    pub object_id: usize,           // tells apart the objects hit, see next_object_id; 0 if unknown
 }
 
 impl HitRecord {
-    pub fn new(p: Vec3, normal: Vec3, mat: Rc<dyn Material>, t: f64, front_face: bool) -> Self {
-        HitRecord{p, normal, mat, t, front_face}
+    ///Create a hit record. The surface and barycentric coordinates and the object id default to zero.
+    pub fn new(p: Vec3, normal: Vec3, mat: Arc<dyn Material>, t: f64, front_face: bool) -> Self {
+        HitRecord{p, normal, mat, t, front_face, u: 0.0, v: 0.0, barycentric: (0.0, 0.0), object_id: 0}
     }
 
     ///Set the hit record normal vector and direction it faces. 
@@ -31,27 +50,115 @@ impl HitRecord {
     }
 }
 
-pub trait Hittable {
-    fn hit(self: &Self, r: &Ray, ray_t: Interval) -> Option<HitRecord>;
+///The id the next object created gets, counting from 1 as 0 is unknown
+static NEXT_OBJECT_ID: AtomicUsize = AtomicUsize::new(1);
+
+///A new id for an object, which differs from that of every other object.
+///
+///Ids count up as objects are created, so a scene built the same way gets
+/// the same ids from run to run.
+pub fn next_object_id() -> usize {
+    NEXT_OBJECT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+///Anything a ray can hit. 
+/// 
+///Hittables are shared between render threads, so must be Send + Sync.
+/// The rng is for hittables hit at random, such as participating media.
+pub trait Hittable: Send + Sync {
+    fn hit(self: &Self, r: &Ray, ray_t: Interval, _rng: &mut RenderRng) -> Option<HitRecord>;
+
+    ///Returns a box enclosing everything the hittable could be hit on 
+    fn bounding_box(self: &Self) -> Aabb;
+
+    ///The fraction of light, per channel, passing along the ray within ray_t
+    /// unscattered and unabsorbed, e.g. to test a shadow ray. It may be a
+    /// random estimate whose mean is the true fraction. 
+    /// 
+    ///The default suits solid objects: all light passes if the ray misses, 
+    /// and none if it hits. Participating media let some light through, and 
+    /// groups of hittables combine the fractions of their members.
+    fn transmittance(self: &Self, r: &Ray, ray_t: Interval, rng: &mut RenderRng) -> Color {
+        if self.hit(r, ray_t, rng).is_some() {
+            Color::new_zeroes()
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        }
+    }
+
+    ///The probability density, per unit solid angle, of random returning
+    /// direction from origin at the given time. 
+    /// 
+    ///Hittables that can be sampled as lights override this and random; the
+    /// default of 0 is never picked.
+    fn pdf_value(self: &Self, _origin: &Vec3, _direction: &Vec3, _time: f64, _rng: &mut RenderRng) -> f64 {
+        0.0
+    }
+
+    ///Returns a random direction from origin towards the hittable where it is
+    /// at the given time, not necessarily of unit length
+    fn random(self: &Self, _origin: &Vec3, _time: f64, _rng: &mut RenderRng) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
 } 
 
+///A sphere, which may move in a straight line while the camera's shutter is open
 #[derive(Clone, Debug)]
 pub struct Sphere {
-    center: Vec3,
+    center: Ray,    // the center at time 0, moving to origin + direction at time 1
     radius: f64,
-    mat: Rc<dyn Material>,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+    id: usize,
 }
 
 impl Sphere {
     //  Constructors
-    pub fn new (center: Vec3, radius: f64, mat: Rc<dyn Material>) -> Sphere {
-        Sphere {center, radius, mat} //Using the Field Init Shorthand 
+    pub fn new (center: Vec3, radius: f64, mat: Arc<dyn Material>) -> Sphere {
+        let rvec = Vec3::new(radius, radius, radius);
+        let bbox = Aabb::new_from_points(center - rvec, center + rvec);
+        Sphere {center: Ray::new(center, Vec3::new_zeroes()), radius, mat, bbox, id: next_object_id()} //Using the Field Init Shorthand 
+    }
+
+    ///Create a sphere moving from center1 at time 0 to center2 at time 1
+    pub fn new_moving(center1: Vec3, center2: Vec3, radius: f64, mat: Arc<dyn Material>) -> Sphere {
+        let rvec = Vec3::new(radius, radius, radius);
+        //Enclose the sphere at both ends of its path
+        let box1 = Aabb::new_from_points(center1 - rvec, center1 + rvec);
+        let box2 = Aabb::new_from_points(center2 - rvec, center2 + rvec);
+        let bbox = Aabb::new_enclosing(&box1, &box2);
+        Sphere {center: Ray::new(center1, center2 - center1), radius, mat, bbox, id: next_object_id()}
+    }
+
+    //Methods
+    ///The cosine of the half angle of the cone of directions from origin to
+    /// the sphere about center, or None if origin is inside the sphere, which 
+    /// then fills every direction. 
+    fn cos_theta_max(self: &Self, center: &Vec3, origin: &Vec3) -> Option<f64> {
+        let distance_squared = (*center - *origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return None;
+        }
+        Some((1.0 - radius_squared / distance_squared).sqrt())
+    }
+
+    //Associated functions
+    ///Returns the surface coordinates (u, v) of a point p on the unit sphere.
+    /// 
+    ///u runs from 0 to 1 anticlockwise about the y axis (seen from above), 
+    /// starting from -x; v runs from 0 at y = -1 to 1 at y = +1. 
+    fn get_sphere_uv(p: &Vec3) -> (f64, f64) {
+        let theta = (-p.y).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+        (phi / (2.0 * PI), theta / PI)
     }
 }
 
 impl Hittable for Sphere {
-    fn hit(self: &Self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
-        let oc = self.center - *r.origin();
+    fn hit(self: &Self, r: &Ray, ray_t: Interval, _rng: &mut RenderRng) -> Option<HitRecord> {
+        let current_center = self.center.point_at(r.time());
+        let oc = current_center - *r.origin();
         let a = r.direction().length_squared();
         let h = Vec3::dot(r.direction(), &oc);
         let c = oc.length_squared() - self.radius * self.radius;
@@ -73,35 +180,70 @@ impl Hittable for Sphere {
         }
 
         let mut hit_record = HitRecord::new(r.point_at(root), Vec3::new_zeroes(), self.mat.clone(), root, false);
-        let outward_normal = (r.point_at(root) - self.center) / self.radius;
+        let outward_normal = (r.point_at(root) - current_center) / self.radius;
         hit_record.set_face_normal(r, outward_normal);
+        (hit_record.u, hit_record.v) = Sphere::get_sphere_uv(&outward_normal);
+        hit_record.object_id = self.id;
+
+        Some(hit_record)
+    }
+
+    fn bounding_box(self: &Self) -> Aabb {
+        self.bbox
+    }
 
-        return Some(hit_record);
+    fn pdf_value(self: &Self, origin: &Vec3, direction: &Vec3, time: f64, rng: &mut RenderRng) -> f64 {
+        if self.hit(&Ray::new_with_time(*origin, *direction, time), Interval::new(0.001, f64::INFINITY), rng).is_none() {
+            return 0.0;
+        }
+
+        match self.cos_theta_max(&self.center.point_at(time), origin) {
+            Some(cos_theta_max) => 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+            None => 1.0 / (4.0 * PI),
+        }
+    }
+
+    fn random(self: &Self, origin: &Vec3, time: f64, rng: &mut RenderRng) -> Vec3 {
+        let center = self.center.point_at(time);
+        let Some(cos_theta_max) = self.cos_theta_max(&center, origin) else {
+            return Vec3::random_unit_vector(rng);
+        };
+
+        //Pick a direction uniformly in the cone of directions seen from origin
+        let z = 1.0 + rng.gen::<f64>() * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * rng.gen::<f64>();
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+        let local = Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z);
+        Onb::new(&(center - *origin)).transform(&local)
     }
-    
 }
 
 pub struct HittableList {
-    pub objects: Vec<Rc<dyn Hittable>>,
+    pub objects: Vec<Arc<dyn Hittable>>,
+    bbox: Aabb,
 }
 
 impl HittableList {
     //Constructors
     pub fn new_empty() -> Self {
-        HittableList{ objects: Vec::new(), }
+        HittableList{ objects: Vec::new(), bbox: Aabb::EMPTY, }
     }
 
-    pub fn new_with_element(elem: Rc<dyn Hittable>) -> Self {
-        HittableList{ objects: vec![elem], }
+    pub fn new_with_element(elem: Arc<dyn Hittable>) -> Self {
+        let bbox = elem.bounding_box();
+        HittableList{ objects: vec![elem], bbox, }
     }
 
     //methods
     pub fn clear(self: &mut Self) {
         self.objects.clear();
+        self.bbox = Aabb::EMPTY;
     }
 
-    pub fn add(self: &mut Self, elem: Rc<dyn Hittable>) {
-        //This method moves the Rc<dyn Hittable> value elem by
+    pub fn add(self: &mut Self, elem: Arc<dyn Hittable>) {
+        //Grow the list's bounding box to enclose the new element
+        self.bbox = Aabb::new_enclosing(&self.bbox, &elem.bounding_box());
+        //This method moves the Arc<dyn Hittable> value elem by
         // taking ownership of it then transferring ownership
         // to the self.objects vector 
         self.objects.push(elem);
@@ -109,7 +251,7 @@ impl HittableList {
 }
 
 impl Hittable for HittableList {
-    fn hit(self: &Self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+    fn hit(self: &Self, r: &Ray, ray_t: Interval, rng: &mut RenderRng) -> Option<HitRecord> {
         //Work through the list to see if any object is hit. 
         //Return the one closest to the ray's origin. 
         let mut closest_so_far = ray_t.max; 
@@ -120,7 +262,7 @@ impl Hittable for HittableList {
         for hittable in &self.objects {
             //If the ray hits the current Hittable object AND does so
             // closer to the ray origin, make this Hittable our closest_hit  
-            let did_ray_hit = hittable.hit(r, Interval::new(ray_t.min, closest_so_far));
+            let did_ray_hit = hittable.hit(r, Interval::new(ray_t.min, closest_so_far), rng);
             if let Some(hit) = &did_ray_hit {
                 //We have a closer hit, so record this
                 closest_so_far = hit.t;
@@ -132,4 +274,146 @@ impl Hittable for HittableList {
         // ray hit, or None if the ray missed them all
         closest_hit
     }
+
+    fn bounding_box(self: &Self) -> Aabb {
+        self.bbox
+    }
+
+    fn transmittance(self: &Self, r: &Ray, ray_t: Interval, rng: &mut RenderRng) -> Color {
+        //Light must pass every object, so multiply their transmittances, 
+        // stopping as soon as one blocks the ray entirely
+        let mut transmittance = Color::new(1.0, 1.0, 1.0);
+        for hittable in &self.objects {
+            transmittance = transmittance * hittable.transmittance(r, ray_t, rng);
+            if transmittance == Color::new_zeroes() {
+                break;
+            }
+        }
+        transmittance
+    }
+
+    ///Each object is equally likely to be sampled
+    fn pdf_value(self: &Self, origin: &Vec3, direction: &Vec3, time: f64, rng: &mut RenderRng) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self.objects.iter().map(|object| object.pdf_value(origin, direction, time, rng)).sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn random(self: &Self, origin: &Vec3, time: f64, rng: &mut RenderRng) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+        self.objects[rng.gen_range(0..self.objects.len())].random(origin, time, rng)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn sphere_hit_records_surface_coordinates() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -5.0), 1.0, mat);
+        //Rays towards the sphere's +x side and its top
+        let side = Ray::new(Vec3::new(5.0, 0.0, -5.0), Vec3::new(-1.0, 0.0, 0.0));
+        let top = Ray::new(Vec3::new(0.0, 5.0, -5.0), Vec3::new(0.0, -1.0, 0.0));
+
+        //Act
+        let side_hit = sphere.hit(&side, Interval::new(0.001, f64::INFINITY), &mut rng).unwrap();
+        let top_hit = sphere.hit(&top, Interval::new(0.001, f64::INFINITY), &mut rng).unwrap();
+
+        //Assert
+        assert!((side_hit.u - 0.5).abs() < 1e-9 && (side_hit.v - 0.5).abs() < 1e-9);
+        assert!((top_hit.v - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn hits_report_the_id_the_object_was_created_with() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let mat: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let near = Sphere::new(Vec3::new(0.0, 0.0, -5.0), 1.0, mat.clone());
+        let far = Sphere::new(Vec3::new(0.0, 0.0, -10.0), 1.0, mat);
+        let near_copy = near.clone();
+        let r = Ray::new(Vec3::new_zeroes(), Vec3::new(0.0, 0.0, -1.0));
+
+        //Act
+        let near_id = near.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng).unwrap().object_id;
+        let far_id = far.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng).unwrap().object_id;
+        let copy_id = near_copy.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng).unwrap().object_id;
+
+        //Assert
+        //Other tests create objects at the same time, so only the order is known
+        assert!(near_id > 0 && far_id > near_id, "{} then {}", near_id, far_id);
+        assert_eq!(near_id, copy_id);
+    }
+
+    #[test]
+    fn moving_sphere_is_hit_where_it_is_at_the_ray_time() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new_moving(Vec3::new(0.0, 0.0, -5.0), Vec3::new(4.0, 0.0, -5.0), 1.0, mat);
+        let early = Ray::new_with_time(Vec3::new_zeroes(), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let late = Ray::new_with_time(Vec3::new_zeroes(), Vec3::new(0.0, 0.0, -1.0), 1.0);
+
+        //Act
+        let early_hit = sphere.hit(&early, Interval::new(0.001, f64::INFINITY), &mut rng);
+        let late_hit = sphere.hit(&late, Interval::new(0.001, f64::INFINITY), &mut rng);
+
+        //Assert
+        assert!(early_hit.is_some_and(|hit| (hit.t - 4.0).abs() < 1e-9));
+        assert!(late_hit.is_none());
+        assert_eq!((-1.0, 5.0), (sphere.bounding_box().x.min, sphere.bounding_box().x.max));
+    }
+
+    #[test]
+    fn sphere_samples_directions_in_the_cone_it_fills() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -4.0), 2.0, mat);
+        let origin = Vec3::new_zeroes();
+        //The sphere fills a cone of half angle 30 degrees
+        let expected = 1.0 / (2.0 * PI * (1.0 - 0.75f64.sqrt()));
+
+        for _ in 0..100 {
+            //Act
+            let direction = sphere.random(&origin, 0.0, &mut rng);
+            let pdf = sphere.pdf_value(&origin, &direction, 0.0, &mut rng);
+
+            //Assert
+            assert!((pdf - expected).abs() < 1e-9, "pdf {}", pdf);
+        }
+        assert_eq!(0.0, sphere.pdf_value(&origin, &Vec3::new(0.0, 1.0, 0.0), 0.0, &mut rng));
+    }
+
+    #[test]
+    fn moving_sphere_samples_directions_to_where_it_is_at_the_time() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new_moving(Vec3::new(0.0, 0.0, -4.0), Vec3::new(0.0, 4.0, -4.0), 2.0, mat);
+        let origin = Vec3::new(0.0, 4.0, 0.0);
+        //At time 1 the sphere fills a cone of half angle 30 degrees
+        let expected = 1.0 / (2.0 * PI * (1.0 - 0.75f64.sqrt()));
+
+        for _ in 0..100 {
+            //Act
+            let direction = sphere.random(&origin, 1.0, &mut rng);
+            let pdf = sphere.pdf_value(&origin, &direction, 1.0, &mut rng);
+
+            //Assert
+            assert!((pdf - expected).abs() < 1e-9, "pdf {}", pdf);
+        }
+        //Towards where the sphere was at time 0 there is nothing
+        assert_eq!(0.0, sphere.pdf_value(&origin, &Vec3::new(0.0, -1.0, -1.0), 1.0, &mut rng));
+    }
 }
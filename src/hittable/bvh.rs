@@ -0,0 +1,239 @@
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, HittableList};
+use crate::interval::Interval;
+use crate::random::RenderRng;
+use crate::ray::Ray;
+use crate::vec3::color::Color;
+
+thread_local! {
+    //The number of BVH nodes whose boxes this thread has tested for hits,
+    // or None when not counting them; see count_node_visits
+    static NODE_VISITS: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+///Calls f, returning its result and the number of BVH nodes visited by hit
+/// on this thread meanwhile, a measure of how much work finding hits took.
+///
+///Nodes are only counted within f, so other renders do not pay for it.
+pub fn count_node_visits<T>(f: impl FnOnce() -> T) -> (T, u64) {
+    let outer = NODE_VISITS.replace(Some(0));
+    let result = f();
+    let visits = NODE_VISITS.get().unwrap_or(0);
+    //Visits counted here are also part of any count this one is nested in
+    NODE_VISITS.set(outer.map(|outer_visits| outer_visits + visits));
+    (result, visits)
+}
+
+///A node in a bounding volume hierarchy (BVH).
+///
+///Each node holds two children, which are either further BvhNodes or the
+/// scene's hittable objects, and a bounding box enclosing both. A ray that
+/// misses a node's box cannot hit anything below it, so whole branches of
+/// the scene are skipped at once.
+pub struct BvhNode {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    //Constructors
+    ///Build a BVH over all of the objects in the list
+    pub fn new(list: HittableList) -> Self {
+        let mut objects = list.objects;
+        BvhNode::new_from_objects(&mut objects)
+    }
+
+    ///Build a BVH over a slice of objects, reordering the slice as it goes.
+    ///
+    ///Objects are split into two groups at the position that minimises the
+    /// surface area heuristic (SAH) cost estimate, searched over all three axes.
+    pub fn new_from_objects(objects: &mut [Arc<dyn Hittable>]) -> Self {
+        match objects.len() {
+            0 => {
+                let empty: Arc<dyn Hittable> = Arc::new(HittableList::new_empty());
+                BvhNode { left: empty.clone(), right: empty, bbox: Aabb::EMPTY }
+            }
+            1 => {
+                //A single object goes in both children, so nodes never need
+                // to check for a missing child
+                let bbox = objects[0].bounding_box();
+                BvhNode { left: objects[0].clone(), right: objects[0].clone(), bbox }
+            }
+            _ => {
+                let (axis, split) = BvhNode::sah_split(objects);
+                BvhNode::sort_by_centroid(objects, axis);
+                let (left_objects, right_objects) = objects.split_at_mut(split);
+
+                let left = BvhNode::new_child(left_objects);
+                let right = BvhNode::new_child(right_objects);
+                let bbox = Aabb::new_enclosing(&left.bounding_box(), &right.bounding_box());
+                BvhNode { left, right, bbox }
+            }
+        }
+    }
+
+    //Associated functions
+    //Single objects are used as children directly, rather than wrapping them in a node
+    fn new_child(objects: &mut [Arc<dyn Hittable>]) -> Arc<dyn Hittable> {
+        if objects.len() == 1 {
+            objects[0].clone()
+        } else {
+            Arc::new(BvhNode::new_from_objects(objects))
+        }
+    }
+
+    fn sort_by_centroid(objects: &mut [Arc<dyn Hittable>], axis: usize) {
+        objects.sort_by(|a, b| BvhNode::centroid_compare(a, b, axis));
+    }
+
+    fn centroid_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>, axis: usize) -> Ordering {
+        let a_centroid = a.bounding_box().centroid()[axis];
+        let b_centroid = b.bounding_box().centroid()[axis];
+        a_centroid.total_cmp(&b_centroid)
+    }
+
+    ///Returns the axis to sort along and the number of objects to put in the
+    /// left child which give the lowest SAH cost.
+    ///
+    ///The cost of a split is the number of objects on each side weighted by the
+    /// surface area of their bounding box, which is proportional to the chance
+    /// of a random ray hitting that box.
+    fn sah_split(objects: &mut [Arc<dyn Hittable>]) -> (usize, usize) {
+        let n = objects.len();
+        let mut best = (f64::INFINITY, 0, n / 2);
+
+        for axis in 0..3 {
+            BvhNode::sort_by_centroid(objects, axis);
+
+            //right_areas[i] is the area of the box around objects[i..]
+            let mut right_areas = vec![0.0; n];
+            let mut right_box = Aabb::EMPTY;
+            for i in (1..n).rev() {
+                right_box = Aabb::new_enclosing(&right_box, &objects[i].bounding_box());
+                right_areas[i] = right_box.surface_area();
+            }
+
+            //Sweep the split position from left to right
+            let mut left_box = Aabb::EMPTY;
+            for split in 1..n {
+                left_box = Aabb::new_enclosing(&left_box, &objects[split - 1].bounding_box());
+                let cost = split as f64 * left_box.surface_area()
+                    + (n - split) as f64 * right_areas[split];
+                if cost < best.0 {
+                    best = (cost, axis, split);
+                }
+            }
+        }
+
+        (best.1, best.2)
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(self: &Self, r: &Ray, ray_t: Interval, rng: &mut RenderRng) -> Option<HitRecord> {
+        if let Some(visits) = NODE_VISITS.get() {
+            NODE_VISITS.set(Some(visits + 1));
+        }
+        if !self.bbox.hit(r, ray_t) {
+            return None;
+        }
+
+        //Only accept hits on the right child that are closer than any left hit
+        let hit_left = self.left.hit(r, ray_t, rng);
+        let closest_so_far = hit_left.as_ref().map_or(ray_t.max, |hit| hit.t);
+        let hit_right = self.right.hit(r, Interval::new(ray_t.min, closest_so_far), rng);
+
+        hit_right.or(hit_left)
+    }
+
+    fn transmittance(self: &Self, r: &Ray, ray_t: Interval, rng: &mut RenderRng) -> Color {
+        if !self.bbox.hit(r, ray_t) {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+
+        let left = self.left.transmittance(r, ray_t, rng);
+        //A leaf holds the same object in both children
+        if Arc::ptr_eq(&self.left, &self.right) || left == Color::new_zeroes() {
+            return left;
+        }
+        left * self.right.transmittance(r, ray_t, rng)
+    }
+
+    fn bounding_box(self: &Self) -> Aabb {
+        self.bbox
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+
+    use super::*;
+    use crate::hittable::Sphere;
+    use crate::material::{Lambertian, Material};
+    use crate::random::new_rng;
+    use crate::vec3::{color::Color, Vec3};
+
+    #[test]
+    fn bvh_finds_same_closest_hits_as_list() {
+        //Arrange
+        let mut rng = new_rng(7);
+        let mat: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut list = HittableList::new_empty();
+        let mut list_copy = HittableList::new_empty();
+        for _ in 0..200 {
+            let center = Vec3::new_random_in_range(-10.0, 10.0, &mut rng);
+            let sphere: Arc<dyn Hittable> = Arc::new(Sphere::new(center, rng.gen_range(0.1..1.0), mat.clone()));
+            list.add(sphere.clone());
+            list_copy.add(sphere);
+        }
+        let bvh = BvhNode::new(list_copy);
+
+        for _ in 0..500 {
+            let r = Ray::new(
+                Vec3::new_random_in_range(-15.0, 15.0, &mut rng),
+                Vec3::random_unit_vector(&mut rng),
+            );
+
+            //Act
+            let expected = list.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng).map(|hit| hit.t);
+            let result = bvh.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng).map(|hit| hit.t);
+
+            //Assert
+            assert_eq!(expected, result);
+        }
+    }
+
+    #[test]
+    fn count_node_visits_counts_the_nodes_tested() {
+        //Arrange
+        let mut rng = new_rng(0);
+        let mat: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut list = HittableList::new_empty();
+        for x in 0..4 {
+            list.add(Arc::new(Sphere::new(Vec3::new(3.0 * x as f64, 0.0, 0.0), 1.0, mat.clone())));
+        }
+        let bvh = BvhNode::new(list);
+        let r = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let miss = Ray::new(Vec3::new(0.0, 5.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        //Act
+        let (_, hit_visits) = count_node_visits(|| bvh.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng));
+        let (_, miss_visits) = count_node_visits(|| bvh.hit(&miss, Interval::new(0.001, f64::INFINITY), &mut rng));
+        bvh.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng);
+
+        //Assert
+        //The hit goes down to the leaf pair holding the first sphere, and
+        // tests the other pair's box; the miss stops at the root
+        assert_eq!(3, hit_visits);
+        assert_eq!(1, miss_visits);
+        //Hits outside a count are not counted
+        assert_eq!(None, NODE_VISITS.get());
+    }
+}
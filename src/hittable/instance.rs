@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{next_object_id, HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::random::RenderRng;
+use crate::ray::Ray;
+use crate::transform::Transform;
+use crate::vec3::{color::Color, Vec3};
+
+///A hittable placed in the world by a transform.
+///
+///The same object can be shared by many instances, each with its own pose.
+/// Rays are moved into the object's space to be tested, and hits are moved
+/// back out into world space.
+///
+///An instance can also move, from one pose at time 0 to another at time 1.
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    transform: Transform,           // object space to world space, at time 0
+    end_transform: Option<Transform>,   // the same at time 1, if the instance moves
+    bbox: Aabb,
+    id: usize,
+}
+
+impl Instance {
+    //Constructors
+    pub fn new(object: Arc<dyn Hittable>, transform: Transform) -> Self {
+        let bbox = transform.transform_box(&object.bounding_box()).pad_to_minimums();
+        Instance { object, transform, end_transform: None, bbox, id: next_object_id() }
+    }
+
+    ///Create an instance moving from the start pose at time 0 to the end pose
+    /// at time 1. The matrices are interpolated, see Transform::lerp.
+    pub fn new_moving(object: Arc<dyn Hittable>, start: Transform, end: Transform) -> Self {
+        //Each transformed corner moves in a straight line, so the boxes at
+        // the two ends enclose the whole motion
+        let object_box = object.bounding_box();
+        let bbox = Aabb::new_enclosing(&start.transform_box(&object_box), &end.transform_box(&object_box))
+            .pad_to_minimums();
+        Instance { object, transform: start, end_transform: Some(end), bbox, id: next_object_id() }
+    }
+
+    //Methods
+    //The object to world transform at the given time, if it can be inverted
+    fn transform_at(self: &Self, time: f64) -> Option<Transform> {
+        match &self.end_transform {
+            Some(end) => self.transform.lerp(end, time),
+            None => Some(self.transform),
+        }
+    }
+
+    //Associated functions
+    //The direction is not normalised, so t is the same in both spaces
+    fn to_object_space(transform: &Transform, r: &Ray) -> Ray {
+        let to_object = transform.inverse();
+        Ray::new_with_time(
+            to_object.transform_point(r.origin()), 
+            to_object.transform_vector(r.direction()), 
+            r.time())
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(self: &Self, r: &Ray, ray_t: Interval, rng: &mut RenderRng) -> Option<HitRecord> {
+        let transform = self.transform_at(r.time())?;
+        let object_r = Instance::to_object_space(&transform, r);
+
+        let mut hit_record = self.object.hit(&object_r, ray_t, rng)?;
+
+        //The normal already faces against the object space ray, and the
+        // inverse-transpose keeps it facing against the world space ray
+        hit_record.p = transform.transform_point(&hit_record.p);
+        hit_record.normal = Vec3::unit_vector(&transform.transform_normal(&hit_record.normal));
+        //Tell apart the copies of an object placed by different instances
+        hit_record.object_id = hit_record.object_id.rotate_left(16) ^ self.id;
+
+        Some(hit_record)
+    }
+
+    fn bounding_box(self: &Self) -> Aabb {
+        self.bbox
+    }
+
+    fn transmittance(self: &Self, r: &Ray, ray_t: Interval, rng: &mut RenderRng) -> Color {
+        match self.transform_at(r.time()) {
+            Some(transform) => self.object.transmittance(&Instance::to_object_space(&transform, r), ray_t, rng),
+            None => Color::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hittable::planar::axis_aligned_box;
+    use crate::hittable::Sphere;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn instance_moves_hits_and_bounding_box() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere: Arc<dyn Hittable> = Arc::new(Sphere::new(Vec3::new_zeroes(), 1.0, mat));
+        let transform = Transform::scale(Vec3::new(2.0, 1.0, 1.0))
+            .then(&Transform::translate(Vec3::new(0.0, 0.0, -5.0)));
+        let instance = Instance::new(sphere, transform);
+        let r = Ray::new(Vec3::new(10.0, 0.0, -5.0), Vec3::new(-1.0, 0.0, 0.0));
+
+        //Act
+        let hit = instance.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng).unwrap();
+        let bbox = instance.bounding_box();
+
+        //Assert
+        assert!((hit.t - 8.0).abs() < 1e-9);
+        assert!((hit.p - Vec3::new(2.0, 0.0, -5.0)).length() < 1e-9);
+        assert!((hit.normal - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+        assert!((bbox.x.min + 2.0).abs() < 1e-9 && (bbox.x.max - 2.0).abs() < 1e-9);
+        assert!((bbox.z.min + 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn moving_instance_is_hit_where_it_is_at_the_ray_time() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let cube: Arc<dyn Hittable> = Arc::new(axis_aligned_box(Vec3::new_zeroes(), Vec3::new(1.0, 1.0, 1.0), mat));
+        let instance = Instance::new_moving(cube,
+            Transform::translate(Vec3::new(0.0, 0.0, -5.0)),
+            Transform::translate(Vec3::new(0.0, 2.0, -5.0)));
+        let direction = Vec3::new(0.0, 0.0, -1.0);
+        let origin = Vec3::new(0.5, 2.5, 0.0);
+
+        //Act
+        let early_hit = instance.hit(&Ray::new_with_time(origin, direction, 0.0), Interval::new(0.001, f64::INFINITY), &mut rng);
+        let late_hit = instance.hit(&Ray::new_with_time(origin, direction, 0.75), Interval::new(0.001, f64::INFINITY), &mut rng);
+
+        //Assert
+        assert!(early_hit.is_none());
+        assert!(late_hit.is_some_and(|hit| (hit.t - 4.0).abs() < 1e-9));
+        assert!(instance.bounding_box().y.max > 2.99);
+    }
+
+    #[test]
+    fn rotated_instance_bounding_box_encloses_object() {
+        //Arrange
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let cube: Arc<dyn Hittable> = Arc::new(axis_aligned_box(Vec3::new_zeroes(), Vec3::new(1.0, 1.0, 1.0), mat));
+
+        //Act
+        let instance = Instance::new(cube, Transform::rotate(Vec3::new(0.0, 1.0, 0.0), 45.0));
+        let bbox = instance.bounding_box();
+
+        //Assert
+        //Seen from above, the square's diagonal now lies along x and it spans
+        // half a diagonal either side of the origin along z. The cube's flat
+        // faces have padded boxes, so allow for the padding.
+        let diagonal = 2.0f64.sqrt();
+        assert!(bbox.x.min.abs() < 1e-3 && (bbox.x.max - diagonal).abs() < 1e-3);
+        assert!((bbox.z.min + 0.5 * diagonal).abs() < 1e-3 && (bbox.z.max - 0.5 * diagonal).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,473 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::aabb::Aabb;
+use crate::density::DensityField;
+use crate::hittable::{next_object_id, HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::{HenyeyGreenstein, Isotropic, Material, ScatterRecord, ScatterSample};
+use crate::random::RenderRng;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::{color::Color, Vec3};
+
+///A participating medium of constant density, such as fog or smoke, filling
+/// the inside of a boundary hittable.
+///
+///A ray passing through the medium is scattered after an exponentially
+/// distributed distance: the denser the medium, the shorter the distance.
+/// Rays that get through without scattering carry on as if it were not there.
+///
+///The boundary must be convex, as rays are assumed to cross it at most once
+/// each way.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    neg_inv_density: f64,       // -1 / density
+    phase_function: Arc<dyn Material>,
+    id: usize,
+}
+
+impl ConstantMedium {
+    //Constructors
+    pub fn new(boundary: Arc<dyn Hittable>, density: f64, albedo: Color) -> Self {
+        ConstantMedium::new_with_phase_function(boundary, density, Arc::new(Isotropic::new(albedo)))
+    }
+
+    pub fn new_textured(boundary: Arc<dyn Hittable>, density: f64, albedo: Arc<dyn Texture>) -> Self {
+        ConstantMedium::new_with_phase_function(boundary, density, Arc::new(Isotropic::new_textured(albedo)))
+    }
+
+    ///Create the medium with any material as its phase function. The material
+    /// is given hit records with an arbitrary normal.
+    pub fn new_with_phase_function(boundary: Arc<dyn Hittable>, density: f64, phase_function: Arc<dyn Material>) -> Self {
+        ConstantMedium { boundary, neg_inv_density: -1.0 / density, phase_function, id: next_object_id() }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(self: &Self, r: &Ray, ray_t: Interval, rng: &mut RenderRng) -> Option<HitRecord> {
+        let (t_entry, t_exit) = span_inside(&*self.boundary, r, ray_t, rng)?;
+
+        let ray_length = r.direction().length();
+        let distance_inside_boundary = (t_exit - t_entry) * ray_length;
+        let hit_distance = self.neg_inv_density * rng.gen::<f64>().ln();
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+
+        //The normal and front_face mean nothing inside a medium, so are arbitrary
+        let t = t_entry + hit_distance / ray_length;
+        let mut hit_record = HitRecord::new(r.point_at(t), Vec3::new(1.0, 0.0, 0.0), self.phase_function.clone(), t, true);
+        hit_record.object_id = self.id;
+        Some(hit_record)
+    }
+
+    fn bounding_box(self: &Self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+
+    fn transmittance(self: &Self, r: &Ray, ray_t: Interval, rng: &mut RenderRng) -> Color {
+        let Some((t_entry, t_exit)) = span_inside(&*self.boundary, r, ray_t, rng) else {
+            return Color::new(1.0, 1.0, 1.0);
+        };
+        let distance_inside_boundary = (t_exit - t_entry) * r.direction().length();
+        let fraction = (distance_inside_boundary / self.neg_inv_density).exp();
+        Color::new(fraction, fraction, fraction)
+    }
+}
+
+///A participating medium whose density varies through space, such as cloud,
+/// filling the inside of a boundary hittable.
+///
+///The density field is scaled by absorption and scattering coefficients
+/// sigma_a and sigma_s, given per channel, and scattered light follows the
+/// Henyey–Greenstein phase function with anisotropy g.
+///
+///Rays are traced with delta tracking: they step between tentative collisions
+/// spaced by a majorant, the greatest extinction anywhere in the medium, and
+/// at each choose to be absorbed, scatter or carry on in proportion to the 
+/// local coefficients. Where the extinction differs between channels the 
+/// choice suits some channels better than others, and the path is weighted 
+/// to make up for it; a ray carrying on is then hit where it was weighted,
+/// and passes straight through, so the weight reaches whatever it hits next.
+/// Shadow rays use ratio tracking, multiplying together the chance of passing
+/// each tentative collision.
+///
+///The boundary must be convex, as rays are assumed to cross it at most once
+/// each way.
+pub struct HeterogeneousMedium {
+    boundary: Arc<dyn Hittable>,
+    density: Arc<dyn DensityField>,
+    sigma_a: Color,
+    sigma_s: Color,
+    majorant: f64,      // an upper bound on the extinction in any channel
+    phase_function: Arc<dyn Material>,
+    id: usize,
+}
+
+impl HeterogeneousMedium {
+    //Constructors
+    ///Create the medium, with coefficients per unit density. 
+    ///
+    /// # Panics
+    /// If a coefficient is negative.
+    pub fn new(boundary: Arc<dyn Hittable>, density: Arc<dyn DensityField>, sigma_a: Color, sigma_s: Color, g: f64) -> Self {
+        let channels = [sigma_a.r(), sigma_a.g(), sigma_a.b(), sigma_s.r(), sigma_s.g(), sigma_s.b()];
+        assert!(channels.iter().all(|&sigma| sigma >= 0.0), "absorption and scattering coefficients must not be negative");
+
+        let sigma_t = sigma_a + sigma_s;
+        let majorant = density.max_density() * sigma_t.r().max(sigma_t.g()).max(sigma_t.b());
+        //The phase function only picks directions; sigma_s sets the colour
+        let phase_function = Arc::new(HenyeyGreenstein::new(Color::new(1.0, 1.0, 1.0), g));
+        HeterogeneousMedium { boundary, density, sigma_a, sigma_s, majorant, phase_function, id: next_object_id() }
+    }
+
+    //Methods
+    ///The distance along the ray to the next tentative collision
+    fn step(self: &Self, ray_length: f64, rng: &mut RenderRng) -> f64 {
+        -(1.0 - rng.gen::<f64>()).ln() / (self.majorant * ray_length)
+    }
+
+    ///The absorption, scattering and null (fictitious) collision coefficients
+    /// at p, which add up to the majorant in every channel
+    fn coefficients(self: &Self, p: &Vec3) -> (Color, Color, Color) {
+        let density = self.density.density(p);
+        let sigma_a = density * self.sigma_a;
+        let sigma_s = density * self.sigma_s;
+        let sigma_t = sigma_a + sigma_s;
+        let sigma_n = Color::new(
+            (self.majorant - sigma_t.r()).max(0.0), 
+            (self.majorant - sigma_t.g()).max(0.0), 
+            (self.majorant - sigma_t.b()).max(0.0));
+        (sigma_a, sigma_s, sigma_n)
+    }
+
+    //Associated functions
+    fn mean(c: &Color) -> f64 {
+        (c.r() + c.g() + c.b()) / 3.0
+    }
+
+    ///Returns weight * sigma / (majorant * probability), the path weight after
+    /// an event with coefficients sigma chosen with the given probability
+    fn reweight(weight: Color, sigma: Color, majorant: f64, probability: f64) -> Color {
+        (1.0 / (majorant * probability)) * weight * sigma
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit(self: &Self, r: &Ray, ray_t: Interval, rng: &mut RenderRng) -> Option<HitRecord> {
+        if self.majorant <= 0.0 {
+            return None;
+        }
+        let (t_entry, t_exit) = span_inside(&*self.boundary, r, ray_t, rng)?;
+        //As in ConstantMedium, the normal and front_face are arbitrary
+        let hit_record = |p: Vec3, mat: Arc<dyn Material>, t: f64| {
+            let mut hit_record = HitRecord::new(p, Vec3::new(1.0, 0.0, 0.0), mat, t, true);
+            hit_record.object_id = self.id;
+            hit_record
+        };
+
+        let ray_length = r.direction().length();
+        let is_grey = |c: &Color| c.r() == c.g() && c.g() == c.b();
+        let mut t = t_entry;
+        loop {
+            t += self.step(ray_length, rng);
+            if t >= t_exit {
+                //The ray got through, as if the medium were not there
+                return None;
+            }
+
+            let p = r.point_at(t);
+            let (sigma_a, sigma_s, sigma_n) = self.coefficients(&p);
+            let (mean_a, mean_s, mean_n) = (HeterogeneousMedium::mean(&sigma_a), HeterogeneousMedium::mean(&sigma_s), HeterogeneousMedium::mean(&sigma_n));
+            let total = mean_a + mean_s + mean_n;
+            let xi = total * rng.gen::<f64>();
+
+            if xi < mean_a {
+                //Absorbed, so no light comes back along this path
+                let absorbed = Arc::new(PassThrough { weight: Color::new_zeroes() });
+                return Some(hit_record(p, absorbed, t));
+            }
+            if xi < mean_a + mean_s {
+                let mat = if is_grey(&sigma_s) {
+                    self.phase_function.clone()
+                } else {
+                    let weight = HeterogeneousMedium::reweight(Color::new(1.0, 1.0, 1.0), sigma_s, self.majorant, mean_s / total);
+                    Arc::new(Weighted { inner: self.phase_function.clone(), weight })
+                };
+                return Some(hit_record(p, mat, t));
+            }
+            //A null collision. Where it is the same in every channel the ray
+            // carries on unweighted; otherwise it is hit here, so that the
+            // weight is carried by the path whatever the ray meets next,
+            // even a surface inside the medium.
+            if !is_grey(&sigma_n) {
+                let weight = HeterogeneousMedium::reweight(Color::new(1.0, 1.0, 1.0), sigma_n, self.majorant, mean_n / total);
+                return Some(hit_record(p, Arc::new(PassThrough { weight }), t));
+            }
+        }
+    }
+
+    fn bounding_box(self: &Self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+
+    fn transmittance(self: &Self, r: &Ray, ray_t: Interval, rng: &mut RenderRng) -> Color {
+        let mut transmittance = Color::new(1.0, 1.0, 1.0);
+        if self.majorant <= 0.0 {
+            return transmittance;
+        }
+        let Some((t_entry, t_exit)) = span_inside(&*self.boundary, r, ray_t, rng) else {
+            return transmittance;
+        };
+
+        //Ratio tracking: each tentative collision is passed with the chance
+        // of it being a null collision
+        let ray_length = r.direction().length();
+        let mut t = t_entry;
+        loop {
+            t += self.step(ray_length, rng);
+            if t >= t_exit || transmittance == Color::new_zeroes() {
+                return transmittance;
+            }
+            let (_, _, sigma_n) = self.coefficients(&r.point_at(t));
+            transmittance = (1.0 / self.majorant) * transmittance * sigma_n;
+        }
+    }
+}
+
+///Finds the part of the ray inside the boundary and within ray_t, which may
+/// start inside. 
+///
+///Returns the ray parameters where it starts and ends, or None if the ray
+/// does not pass inside.
+fn span_inside(boundary: &dyn Hittable, r: &Ray, ray_t: Interval, rng: &mut RenderRng) -> Option<(f64, f64)> {
+    //Find where the whole line of the ray enters and leaves the boundary
+    let entry = boundary.hit(r, Interval::UNIVERSE, rng)?;
+    let exit = boundary.hit(r, Interval::new(entry.t + 0.0001, f64::INFINITY), rng)?;
+
+    //Clip to the part of the ray being tested
+    let t_entry = entry.t.max(ray_t.min).max(0.0);
+    let t_exit = exit.t.min(ray_t.max);
+    if t_entry >= t_exit {
+        return None;
+    }
+    Some((t_entry, t_exit))
+}
+
+///Scatters with the inner material, multiplying its attenuation by weight
+#[derive(Debug)]
+struct Weighted {
+    inner: Arc<dyn Material>,
+    weight: Color,
+}
+
+impl Material for Weighted {
+    fn scatter(self: &Self, r_in: &Ray, hit_record: &HitRecord, rng: &mut RenderRng) -> Option<ScatterRecord> {
+        let scatter_record = self.inner.scatter(r_in, hit_record, rng)?;
+        Some(ScatterRecord { attenuation: self.weight * scatter_record.attenuation, ..scatter_record })
+    }
+
+    fn scattering_pdf(self: &Self, r_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> f64 {
+        self.inner.scattering_pdf(r_in, hit_record, scattered)
+    }
+}
+
+///Lets the ray carry straight on, multiplied by weight. Rays are absorbed
+/// where the weight is black.
+#[derive(Debug)]
+struct PassThrough {
+    weight: Color,
+}
+
+impl Material for PassThrough {
+    fn scatter(self: &Self, r_in: &Ray, hit_record: &HitRecord, _rng: &mut RenderRng) -> Option<ScatterRecord> {
+        if self.weight == Color::new_zeroes() {
+            return None;
+        }
+        //Start a little way back, so that with the usual 0.001 offset the
+        // continued ray picks up exactly where this one was hit
+        let continued = Ray::new_with_time(r_in.point_at(hit_record.t - 0.001), *r_in.direction(), r_in.time());
+        Some(ScatterRecord { attenuation: self.weight, sample: ScatterSample::PassThrough(continued) })
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::density::GridDensity;
+    use crate::hittable::planar::axis_aligned_box;
+    use crate::hittable::{HittableList, Sphere};
+    use crate::background::SolidBackground;
+    use crate::hittable::planar::Quad;
+    use crate::integrator::PathTracer;
+    use crate::material::Lambertian;
+    use crate::testing::{grey, mean_color};
+
+    fn fog(density: f64) -> ConstantMedium {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let boundary = Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -5.0), 1.0, mat));
+        ConstantMedium::new(boundary, density, Color::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn constant_medium_scatters_inside_boundary_by_density() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let r = Ray::new(Vec3::new_zeroes(), Vec3::new(0.0, 0.0, -2.0));
+        let (thin, thick) = (fog(0.01), fog(100.0));
+        let mut thin_hits = 0;
+
+        for _ in 0..1000 {
+            //Act
+            let thin_hit = thin.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng);
+            let thick_hit = thick.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng);
+
+            //Assert
+            //The ray crosses the sphere between t = 2 and t = 3
+            if let Some(hit) = thin_hit {
+                assert!((2.0..=3.0).contains(&hit.t));
+                thin_hits += 1;
+            }
+            assert!(thick_hit.is_some_and(|hit| (2.0..2.1).contains(&hit.t)));
+        }
+        //The ray crosses two units of fog, the sphere's diameter, and at density
+        // 0.01 they scatter about 1 - e^-0.02, or 2%, of rays
+        assert!((5..50).contains(&thin_hits), "{} rays scattered", thin_hits);
+    }
+
+    #[test]
+    fn constant_medium_scatters_rays_starting_inside() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        //Act
+        let hit = fog(100.0).hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng);
+
+        //Assert
+        assert!(hit.is_some_and(|hit| hit.t < 0.1));
+    }
+
+    fn unit_cube_of(resolution: [usize; 3], values: Vec<f64>, sigma_a: Color, sigma_s: Color) -> HeterogeneousMedium {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let boundary = Arc::new(axis_aligned_box(Vec3::new_zeroes(), Vec3::new(1.0, 1.0, 1.0), mat));
+        let grid = GridDensity::new(boundary.bounding_box(), resolution, values);
+        HeterogeneousMedium::new(boundary, Arc::new(grid), sigma_a, sigma_s, 0.0)
+    }
+
+    #[test]
+    fn delta_tracking_scatters_at_the_rate_of_the_extinction() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        //Three cells along x, the last denser. Along the ray, through the
+        // centre of the middle cell, the density is half the maximum, so half
+        // the tentative collisions are null.
+        let medium = unit_cube_of([3, 1, 1], vec![1.0, 1.0, 2.0], Color::new_zeroes(), Color::new(1.0, 1.0, 1.0));
+        let r = Ray::new(Vec3::new(0.5, 0.5, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        let trials = 20000;
+        let mut scattered = 0;
+
+        //Act
+        for _ in 0..trials {
+            if let Some(hit) = medium.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng) {
+                assert!((1.0..2.0).contains(&hit.t));
+                scattered += 1;
+            }
+        }
+
+        //Assert
+        //One unit of density 1 scatters 1 - e^-1 of rays crossing it
+        let fraction = scattered as f64 / trials as f64;
+        assert!((fraction - (1.0 - (-1.0f64).exp())).abs() < 0.01, "{} of rays scattered", fraction);
+    }
+
+    #[test]
+    fn ratio_tracking_estimates_transmittance_per_channel() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let medium = unit_cube_of([1, 1, 1], vec![1.0], Color::new(0.5, 1.0, 0.0), Color::new(0.5, 1.0, 0.5));
+        let r = Ray::new(Vec3::new(0.5, 0.5, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        let trials = 20000;
+        let mut sum = Color::new_zeroes();
+
+        //Act
+        for _ in 0..trials {
+            sum = sum + medium.transmittance(&r, Interval::new(0.001, f64::INFINITY), &mut rng);
+        }
+
+        //Assert
+        //Extinctions of 1, 2 and 0.5 across one unit
+        let mean = (1.0 / trials as f64) * sum;
+        assert!((mean.r() - (-1.0f64).exp()).abs() < 0.01, "{:?}", mean);
+        assert!((mean.g() - (-2.0f64).exp()).abs() < 0.01, "{:?}", mean);
+        assert!((mean.b() - (-0.5f64).exp()).abs() < 0.01, "{:?}", mean);
+    }
+
+    #[test]
+    fn surfaces_inside_a_coloured_medium_are_seen_through_each_channel() {
+        //Arrange
+        //A grey floor halfway down a wide slab of medium one unit deep, which
+        // absorbs each channel at a different rate, under a white sky
+        let mut rng = crate::random::new_rng(0);
+        let sigma_a = Color::new(0.2, 0.6, 1.2);
+        let boundary = Arc::new(axis_aligned_box(Vec3::new(-1000.0, -1000.0, -1.0), Vec3::new(1000.0, 1000.0, 0.0), grey()));
+        let grid = GridDensity::new(boundary.bounding_box(), [1, 1, 1], vec![1.0]);
+        let medium: Arc<dyn Hittable> = Arc::new(HeterogeneousMedium::new(boundary, Arc::new(grid), sigma_a, Color::new_zeroes(), 0.0));
+        let floor: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Vec3::new(-1000.0, -1000.0, -0.5), Vec3::new(2000.0, 0.0, 0.0), Vec3::new(0.0, 2000.0, 0.0), grey()));
+        let mut tracer = PathTracer::new();
+        tracer.set_background(Arc::new(SolidBackground::new(Color::new(1.0, 1.0, 1.0))));
+        let r = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+
+        //Light reaches the floor across half a unit, from a direction at angle
+        // theta to the normal across 0.5 / cos(theta), and leaves the same way
+        let expected = |sigma: f64| {
+            let steps = 10000;
+            let seen_from = (1..=steps).map(|i| {
+                let cos_theta = (i as f64 - 0.5) / steps as f64;
+                2.0 * cos_theta * (-0.5 * sigma / cos_theta).exp() / steps as f64
+            }).sum::<f64>();
+            0.5 * (-0.5 * sigma).exp() * seen_from
+        };
+
+        //Either way round, so the medium is hit both before and after the floor
+        for order in [[medium.clone(), floor.clone()], [floor, medium]] {
+            let mut world = HittableList::new_empty();
+            for object in order {
+                world.add(object);
+            }
+
+            //Act
+            let result = mean_color(&tracer, &r, &world, 40000, &mut rng);
+
+            //Assert
+            for (result, sigma) in [(result.r(), sigma_a.r()), (result.g(), sigma_a.g()), (result.b(), sigma_a.b())] {
+                assert!((result - expected(sigma)).abs() < 0.03 * expected(sigma), "{} for sigma {}, expected {}", result, sigma, expected(sigma));
+            }
+        }
+    }
+
+    #[test]
+    fn surface_behind_a_medium_blocks_transmittance() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new_empty();
+        world.add(Arc::new(fog(0.5)));
+        world.add(Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -10.0), 1.0, mat)));
+        let r = Ray::new(Vec3::new_zeroes(), Vec3::new(0.0, 0.0, -1.0));
+
+        //Act
+        let through_fog = world.transmittance(&r, Interval::new(0.001, 8.0), &mut rng);
+        let to_surface = world.transmittance(&r, Interval::new(0.001, f64::INFINITY), &mut rng);
+
+        //Assert
+        //The ray crosses two units of fog at density 0.5 before the sphere
+        let expected = (-1.0f64).exp();
+        assert!((through_fog.r() - expected).abs() < 1e-9);
+        assert_eq!(Color::new_zeroes(), to_surface);
+    }
+}
@@ -0,0 +1,331 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::aabb::Aabb;
+use crate::hittable::{next_object_id, HitRecord, Hittable, HittableList};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::random::RenderRng;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+///The plane through q spanned by the edge vectors u and v, shared by the
+/// flat primitives.
+///
+///Points on the plane are written p = q + alpha * u + beta * v, and each
+/// primitive decides which (alpha, beta) lie inside its shape.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Plane {
+    q: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,        // n / (n . n), used to find alpha and beta
+    normal: Vec3,   // unit normal, u x v normalised
+    d: f64,         // the plane is normal . p = d
+    area: f64,      // of the parallelogram spanned by u and v
+    id: usize,      // of the primitive, see next_object_id
+}
+
+impl Plane {
+    //Constructors
+    pub(crate) fn new(q: Vec3, u: Vec3, v: Vec3) -> Self {
+        let n = Vec3::cross(&u, &v);
+        let normal = Vec3::unit_vector(&n);
+        let d = Vec3::dot(&normal, &q);
+        let w = n / Vec3::dot(&n, &n);
+        Plane { q, u, v, w, normal, d, area: n.length(), id: next_object_id() }
+    }
+
+    //Methods
+    ///Intersect the ray with the plane.
+    ///
+    ///Returns the ray parameter t and the plane coordinates (alpha, beta) of
+    /// the hit point, or None if the ray is parallel to the plane or meets it
+    /// outside ray_t.
+    pub(crate) fn hit(self: &Self, r: &Ray, ray_t: Interval) -> Option<(f64, f64, f64)> {
+        let denom = Vec3::dot(&self.normal, r.direction());
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - Vec3::dot(&self.normal, r.origin())) / denom;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        let planar_hitpt_vector = r.point_at(t) - self.q;
+        let alpha = Vec3::dot(&self.w, &Vec3::cross(&planar_hitpt_vector, &self.v));
+        let beta = Vec3::dot(&self.w, &Vec3::cross(&self.u, &planar_hitpt_vector));
+        Some((t, alpha, beta))
+    }
+
+    ///Create the record for a hit at t, with surface coordinates (u, v)
+    pub(crate) fn hit_record(self: &Self, r: &Ray, t: f64, mat: &Arc<dyn Material>, u: f64, v: f64) -> HitRecord {
+        let mut hit_record = HitRecord::new(r.point_at(t), Vec3::new_zeroes(), mat.clone(), t, false);
+        hit_record.set_face_normal(r, self.normal);
+        hit_record.u = u;
+        hit_record.v = v;
+        hit_record.object_id = self.id;
+        hit_record
+    }
+
+    ///The area of the parallelogram spanned by u and v
+    pub(crate) fn area(self: &Self) -> f64 {
+        self.area
+    }
+
+    ///The point at plane coordinates (alpha, beta)
+    pub(crate) fn point_at(self: &Self, alpha: f64, beta: f64) -> Vec3 {
+        self.q + alpha * self.u + beta * self.v
+    }
+
+    ///The density, per unit solid angle, of the direction from origin to the
+    /// point where it meets a shape, of the given area, at ray parameter t,
+    /// when points on the shape are picked uniformly by area
+    pub(crate) fn solid_angle_pdf(self: &Self, direction: &Vec3, t: f64, shape_area: f64) -> f64 {
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = (Vec3::dot(direction, &self.normal) / direction.length()).abs();
+        if cosine < 1e-8 {
+            return 0.0;
+        }
+        distance_squared / (cosine * shape_area)
+    }
+}
+
+///A parallelogram with one corner at q and sides u and v.
+///
+///The outward normal is u x v. The surface coordinates run from (0, 0) at q
+/// to (1, 1) at the opposite corner.
+#[derive(Clone, Debug)]
+pub struct Quad {
+    plane: Plane,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Quad {
+    //Constructors
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Self {
+        //Find the box enclosing all four corners. Quads can lie flat in an
+        // axis plane, so it is padded.
+        let bbox_diagonal1 = Aabb::new_from_points(q, q + u + v);
+        let bbox_diagonal2 = Aabb::new_from_points(q + u, q + v);
+        let bbox = Aabb::new_enclosing(&bbox_diagonal1, &bbox_diagonal2).pad_to_minimums();
+        Quad { plane: Plane::new(q, u, v), mat, bbox }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(self: &Self, r: &Ray, ray_t: Interval, _rng: &mut RenderRng) -> Option<HitRecord> {
+        let (t, alpha, beta) = self.plane.hit(r, ray_t)?;
+
+        let unit_interval = Interval::new(0.0, 1.0);
+        if !unit_interval.contains(alpha) || !unit_interval.contains(beta) {
+            return None;
+        }
+
+        Some(self.plane.hit_record(r, t, &self.mat, alpha, beta))
+    }
+
+    fn bounding_box(self: &Self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(self: &Self, origin: &Vec3, direction: &Vec3, time: f64, rng: &mut RenderRng) -> f64 {
+        match self.hit(&Ray::new_with_time(*origin, *direction, time), Interval::new(0.001, f64::INFINITY), rng) {
+            Some(hit_record) => self.plane.solid_angle_pdf(direction, hit_record.t, self.plane.area()),
+            None => 0.0,
+        }
+    }
+
+    fn random(self: &Self, origin: &Vec3, _time: f64, rng: &mut RenderRng) -> Vec3 {
+        self.plane.point_at(rng.gen(), rng.gen()) - *origin
+    }
+}
+
+///A flat circular disk, facing along normal.
+///
+///The surface coordinates map the unit square onto the square enclosing the
+/// disk, so an image is laid flat across it.
+#[derive(Clone, Debug)]
+pub struct Disk {
+    plane: Plane,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Disk {
+    //Constructors
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, mat: Arc<dyn Material>) -> Self {
+        //Build two perpendicular radius vectors in the disk, with u x v along normal
+        let n = Vec3::unit_vector(&normal);
+        let a = if n.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let u = Vec3::unit_vector(&Vec3::cross(&a, &n));
+        let v = Vec3::cross(&n, &u);
+
+        //The disk reaches radius * sqrt(1 - n_i^2) from its center along each axis
+        let extent = Vec3::new(
+            radius * (1.0 - n.x * n.x).max(0.0).sqrt(),
+            radius * (1.0 - n.y * n.y).max(0.0).sqrt(),
+            radius * (1.0 - n.z * n.z).max(0.0).sqrt(),
+        );
+        let bbox = Aabb::new_from_points(center - extent, center + extent).pad_to_minimums();
+
+        Disk { plane: Plane::new(center, radius * u, radius * v), mat, bbox }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(self: &Self, r: &Ray, ray_t: Interval, _rng: &mut RenderRng) -> Option<HitRecord> {
+        //alpha and beta are measured in radii from the center
+        let (t, alpha, beta) = self.plane.hit(r, ray_t)?;
+        if alpha * alpha + beta * beta > 1.0 {
+            return None;
+        }
+
+        Some(self.plane.hit_record(r, t, &self.mat, 0.5 * (alpha + 1.0), 0.5 * (beta + 1.0)))
+    }
+
+    fn bounding_box(self: &Self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(self: &Self, origin: &Vec3, direction: &Vec3, time: f64, rng: &mut RenderRng) -> f64 {
+        //u and v are perpendicular radii, so span a square of area r^2
+        match self.hit(&Ray::new_with_time(*origin, *direction, time), Interval::new(0.001, f64::INFINITY), rng) {
+            Some(hit_record) => self.plane.solid_angle_pdf(direction, hit_record.t, PI * self.plane.area()),
+            None => 0.0,
+        }
+    }
+
+    fn random(self: &Self, origin: &Vec3, _time: f64, rng: &mut RenderRng) -> Vec3 {
+        let p = Vec3::random_in_unit_disk(rng);
+        self.plane.point_at(p.x, p.y) - *origin
+    }
+}
+
+///Returns the six quads of the axis-aligned box with opposite corners a and b
+pub fn axis_aligned_box(a: Vec3, b: Vec3, mat: Arc<dyn Material>) -> HittableList {
+    let min = Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+    let max = Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+    let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+    let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+    let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+    oriented_box(min, dx, dy, dz, mat)
+}
+
+///Returns the six quads of the box (a parallelepiped) with one corner at
+/// corner and edges x, y and z leaving it. Every face's normal points out.
+pub fn oriented_box(corner: Vec3, x: Vec3, y: Vec3, z: Vec3, mat: Arc<dyn Material>) -> HittableList {
+    //The faces below assume right-handed edges; swapping two edges makes
+    // left-handed ones right-handed
+    let (x, z) = if Vec3::dot(&Vec3::cross(&x, &y), &z) < 0.0 { (z, x) } else { (x, z) };
+
+    let mut sides = HittableList::new_empty();
+    sides.add(Arc::new(Quad::new(corner + z, x, y, mat.clone())));          // front
+    sides.add(Arc::new(Quad::new(corner + x + z, -z, y, mat.clone())));     // right
+    sides.add(Arc::new(Quad::new(corner + x, -x, y, mat.clone())));         // back
+    sides.add(Arc::new(Quad::new(corner, z, y, mat.clone())));              // left
+    sides.add(Arc::new(Quad::new(corner + y + z, x, -z, mat.clone())));     // top
+    sides.add(Arc::new(Quad::new(corner, x, z, mat)));                      // bottom
+    sides
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::grey;
+
+    #[test]
+    fn quad_hit_reports_uv_and_front_face() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        //A 2 x 2 quad in the plane z = -1, facing +z
+        let quad = Quad::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), grey());
+        let from_front = Ray::new(Vec3::new(0.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let from_behind = Ray::new(Vec3::new(0.5, 0.0, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let outside = Ray::new(Vec3::new(1.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        //Act
+        let front_hit = quad.hit(&from_front, Interval::new(0.001, f64::INFINITY), &mut rng).unwrap();
+        let back_hit = quad.hit(&from_behind, Interval::new(0.001, f64::INFINITY), &mut rng).unwrap();
+        let missed = quad.hit(&outside, Interval::new(0.001, f64::INFINITY), &mut rng);
+
+        //Assert
+        assert_eq!((0.75, 0.5), (front_hit.u, front_hit.v));
+        assert!(front_hit.front_face);
+        assert!(!back_hit.front_face);
+        assert_eq!(-1.0, back_hit.normal.z);
+        assert!(missed.is_none());
+    }
+
+    #[test]
+    fn disk_hits_inside_its_radius_only() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let disk = Disk::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 1.0, grey());
+        let inside = Ray::new(Vec3::new(0.6, 1.0, 0.6), Vec3::new(0.0, -1.0, 0.0));
+        let corner = Ray::new(Vec3::new(0.8, 1.0, 0.8), Vec3::new(0.0, -1.0, 0.0));
+
+        //Act
+        let inside_hit = disk.hit(&inside, Interval::new(0.001, f64::INFINITY), &mut rng);
+        let corner_hit = disk.hit(&corner, Interval::new(0.001, f64::INFINITY), &mut rng);
+
+        //Assert
+        assert!(inside_hit.is_some_and(|hit| hit.front_face && hit.normal.y == 1.0));
+        assert!(corner_hit.is_none());
+    }
+
+    #[test]
+    fn box_faces_all_point_outwards() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        //Edges given left-handed, so they must be reordered
+        let boxed = oriented_box(Vec3::new_zeroes(),
+            Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0), grey());
+        let center = Vec3::new(0.5, 0.5, 0.5);
+        let directions = [
+            Vec3::new(1.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0),
+        ];
+
+        for direction in directions {
+            //Act
+            //Fire rays in at each face from outside the box
+            let r = Ray::new(center + 2.0 * direction, -direction);
+            let hit = boxed.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng).unwrap();
+
+            //Assert
+            assert!(hit.front_face, "face facing {:?} points inwards", direction);
+            assert_eq!(1.5, hit.t);
+        }
+    }
+
+    #[test]
+    fn quad_light_pdf_integrates_to_one_over_directions_towards_it() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let light = Quad::new(Vec3::new(-1.0, 2.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0), grey());
+        let origin = Vec3::new(0.3, 0.0, 0.2);
+        let samples = 100000;
+
+        //Act
+        //Estimate the integral over the sphere of directions by uniform sampling
+        let mut sum = 0.0;
+        for _ in 0..samples {
+            let direction = Vec3::random_unit_vector(&mut rng);
+            sum += light.pdf_value(&origin, &direction, 0.0, &mut rng) * 4.0 * PI;
+        }
+        let sampled = light.random(&origin, 0.0, &mut rng);
+
+        //Assert
+        let integral = sum / samples as f64;
+        assert!((integral - 1.0).abs() < 0.03, "integral {}", integral);
+        assert!(light.pdf_value(&origin, &sampled, 0.0, &mut rng) > 0.0);
+    }
+}
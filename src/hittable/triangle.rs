@@ -0,0 +1,329 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::aabb::Aabb;
+use crate::hittable::bvh::BvhNode;
+use crate::hittable::planar::Plane;
+use crate::hittable::{next_object_id, HitRecord, Hittable, HittableList};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::random::RenderRng;
+use crate::ray::Ray;
+use crate::vec3::{color::Color, Vec3};
+
+///A single flat-shaded triangle with vertices v0, v1 and v2.
+///
+///The outward normal follows the right hand rule: it points towards a viewer
+/// who sees the vertices in anticlockwise order.
+#[derive(Clone, Debug)]
+pub struct Triangle {
+    vertices: [Vec3; 3],
+    plane: Plane,   // through v0, spanned by the edges to v1 and v2
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Triangle {
+    //Constructors
+    pub fn new(v0: Vec3, v1: Vec3, v2: Vec3, mat: Arc<dyn Material>) -> Self {
+        let bbox = triangle_bounding_box(&v0, &v1, &v2);
+        Triangle { vertices: [v0, v1, v2], plane: Plane::new(v0, v1 - v0, v2 - v0), mat, bbox }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(self: &Self, r: &Ray, ray_t: Interval, _rng: &mut RenderRng) -> Option<HitRecord> {
+        let [v0, v1, v2] = &self.vertices;
+        let (t, b1, b2) = moller_trumbore(v0, v1, v2, r, ray_t)?;
+
+        //The barycentric coordinates are also the plane coordinates. With no 
+        // texture coordinates of its own, the triangle is parameterised by them.
+        let mut hit_record = self.plane.hit_record(r, t, &self.mat, b1, b2);
+        hit_record.barycentric = (b1, b2);
+
+        Some(hit_record)
+    }
+
+    fn bounding_box(self: &Self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(self: &Self, origin: &Vec3, direction: &Vec3, time: f64, rng: &mut RenderRng) -> f64 {
+        match self.hit(&Ray::new_with_time(*origin, *direction, time), Interval::new(0.001, f64::INFINITY), rng) {
+            Some(hit_record) => self.plane.solid_angle_pdf(direction, hit_record.t, 0.5 * self.plane.area()),
+            None => 0.0,
+        }
+    }
+
+    fn random(self: &Self, origin: &Vec3, _time: f64, rng: &mut RenderRng) -> Vec3 {
+        //Pick a point in the parallelogram, folding the far half back onto
+        // the triangle
+        let (b1, b2) = (rng.gen::<f64>(), rng.gen::<f64>());
+        let (b1, b2) = if b1 + b2 > 1.0 { (1.0 - b1, 1.0 - b2) } else { (b1, b2) };
+        self.plane.point_at(b1, b2) - *origin
+    }
+}
+
+///The vertex data of a triangle mesh, shared by all of its triangles
+#[derive(Debug)]
+struct MeshData {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    indices: Vec<[usize; 3]>,
+    mat: Arc<dyn Material>,
+}
+
+///An indexed triangle mesh.
+///
+///Each triangle is three indices into the vertex buffers, so vertices shared
+/// by neighbouring triangles are only stored once. Vertex normals, when
+/// supplied, are interpolated across each triangle for smooth shading.
+/// The triangles are held in their own BVH.
+pub struct TriangleMesh {
+    triangle_count: usize,
+    bvh: BvhNode,
+}
+
+impl TriangleMesh {
+    //Constructors
+    ///Create a mesh from its vertex buffers and triangle indices.
+    ///
+    ///normals and uvs may be empty, otherwise they must have one entry per
+    /// position.
+    ///
+    /// # Panics
+    /// If the normals or uvs are the wrong length, or an index is out of range.
+    pub fn new(positions: Vec<Vec3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[usize; 3]>,
+        mat: Arc<dyn Material>) -> Self {
+        assert!(normals.is_empty() || normals.len() == positions.len(),
+            "mesh needs one normal per vertex position");
+        assert!(uvs.is_empty() || uvs.len() == positions.len(),
+            "mesh needs one uv per vertex position");
+        assert!(indices.iter().flatten().all(|&i| i < positions.len()),
+            "mesh vertex index out of range");
+
+        let triangle_count = indices.len();
+        let mesh = Arc::new(MeshData { positions, normals, uvs, indices, mat });
+        let mut triangles = HittableList::new_empty();
+        for face in 0..triangle_count {
+            triangles.add(Arc::new(MeshTriangle::new(mesh.clone(), face)));
+        }
+
+        TriangleMesh { triangle_count, bvh: BvhNode::new(triangles) }
+    }
+
+    //Methods
+    pub fn triangle_count(self: &Self) -> usize {
+        self.triangle_count
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(self: &Self, r: &Ray, ray_t: Interval, rng: &mut RenderRng) -> Option<HitRecord> {
+        self.bvh.hit(r, ray_t, rng)
+    }
+
+    fn transmittance(self: &Self, r: &Ray, ray_t: Interval, rng: &mut RenderRng) -> Color {
+        self.bvh.transmittance(r, ray_t, rng)
+    }
+
+    fn bounding_box(self: &Self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+}
+
+///One triangle of a TriangleMesh
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+    bbox: Aabb,
+    id: usize,
+}
+
+impl MeshTriangle {
+    fn new(mesh: Arc<MeshData>, face: usize) -> Self {
+        let [i0, i1, i2] = mesh.indices[face];
+        let bbox = triangle_bounding_box(&mesh.positions[i0], &mesh.positions[i1], &mesh.positions[i2]);
+        MeshTriangle { mesh, face, bbox, id: next_object_id() }
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(self: &Self, r: &Ray, ray_t: Interval, _rng: &mut RenderRng) -> Option<HitRecord> {
+        let mesh = &self.mesh;
+        let [i0, i1, i2] = mesh.indices[self.face];
+        let (v0, v1, v2) = (mesh.positions[i0], mesh.positions[i1], mesh.positions[i2]);
+        let (t, b1, b2) = moller_trumbore(&v0, &v1, &v2, r, ray_t)?;
+        let b0 = 1.0 - b1 - b2;
+
+        //The geometric normal decides which side was hit
+        let mut hit_record = HitRecord::new(r.point_at(t), Vec3::new_zeroes(), mesh.mat.clone(), t, false);
+        hit_record.object_id = self.id;
+        let outward_normal = Vec3::unit_vector(&Vec3::cross(&(v1 - v0), &(v2 - v0)));
+        hit_record.set_face_normal(r, outward_normal);
+
+        //Smooth shading: replace it with the interpolated vertex normal,
+        // flipped to the same side as the geometric normal, as files do not
+        // always wind their faces to agree with their normals. Normals that
+        // cancel out, or are zero in the file, leave the geometric normal.
+        if !mesh.normals.is_empty() {
+            let interpolated = b0 * mesh.normals[i0] + b1 * mesh.normals[i1] + b2 * mesh.normals[i2];
+            if !interpolated.near_zero() {
+                let shading_normal = Vec3::unit_vector(&interpolated);
+                let same_side = Vec3::dot(&shading_normal, &hit_record.normal) >= 0.0;
+                hit_record.normal = if same_side { shading_normal } else { -shading_normal };
+            }
+        }
+
+        (hit_record.u, hit_record.v) = if mesh.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            let (uv0, uv1, uv2) = (mesh.uvs[i0], mesh.uvs[i1], mesh.uvs[i2]);
+            (b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0, b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1)
+        };
+        hit_record.barycentric = (b1, b2);
+
+        Some(hit_record)
+    }
+
+    fn bounding_box(self: &Self) -> Aabb {
+        self.bbox
+    }
+}
+
+//Triangles can lie flat in an axis plane, so their boxes are padded
+fn triangle_bounding_box(v0: &Vec3, v1: &Vec3, v2: &Vec3) -> Aabb {
+    let bbox = Aabb::new_from_points(*v0, *v1);
+    Aabb::new_enclosing(&bbox, &Aabb::new_from_points(*v2, *v2)).pad_to_minimums()
+}
+
+///Möller–Trumbore ray/triangle intersection, shared by Triangle and the
+/// triangles of a TriangleMesh.
+///
+///Returns the ray parameter t and the barycentric coordinates (b1, b2) of the
+/// hit point, so that p = (1 - b1 - b2) * v0 + b1 * v1 + b2 * v2.
+fn moller_trumbore(v0: &Vec3, v1: &Vec3, v2: &Vec3, r: &Ray, ray_t: Interval) -> Option<(f64, f64, f64)> {
+    let edge1 = *v1 - *v0;
+    let edge2 = *v2 - *v0;
+    let pvec = Vec3::cross(r.direction(), &edge2);
+    let det = Vec3::dot(&edge1, &pvec);
+
+    //The ray is parallel to the triangle's plane
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = *r.origin() - *v0;
+    let b1 = Vec3::dot(&tvec, &pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = Vec3::cross(&tvec, &edge1);
+    let b2 = Vec3::dot(r.direction(), &qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = Vec3::dot(&edge2, &qvec) * inv_det;
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::grey;
+
+    #[test]
+    fn triangle_hit_records_barycentric_coordinates() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let triangle = Triangle::new(Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(3.0, 0.0, -1.0),
+            Vec3::new(0.0, 3.0, -1.0),
+            grey());
+        let r = Ray::new(Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        //Act
+        let hit = triangle.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng).unwrap();
+
+        //Assert
+        assert!((hit.t - 1.0).abs() < 1e-9);
+        assert!((hit.barycentric.0 - 1.0 / 3.0).abs() < 1e-9);
+        assert!((hit.barycentric.1 - 1.0 / 3.0).abs() < 1e-9);
+        assert!(hit.front_face);
+    }
+
+    #[test]
+    fn triangle_misses_ray_outside_edges() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let triangle = Triangle::new(Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, -1.0),
+            grey());
+        let r = Ray::new(Vec3::new(0.8, 0.8, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        //Act
+        let result = triangle.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng);
+
+        //Assert
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn mesh_interpolates_vertex_normals() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        //A single triangle facing +z, whose vertex normals point along +z, then
+        // lean towards +x and towards +y
+        let positions = vec![Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, -1.0, -1.0), Vec3::new(-1.0, 1.0, -1.0)];
+        let normals = vec![Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 1.0), Vec3::new(0.0, 1.0, 1.0)];
+        let mesh = TriangleMesh::new(positions, normals, Vec::new(), vec![[0, 1, 2]], grey());
+        let r = Ray::new(Vec3::new(0.0, -0.99, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        //Act
+        let hit = mesh.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng).unwrap();
+
+        //Assert
+        //Close to half way along the v0-v1 edge the normal leans towards +x
+        assert!(hit.normal.x > 0.3);
+        assert!(hit.normal.y < 0.01);
+        assert!((hit.normal.length() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mesh_shading_normals_face_the_same_side_as_the_surface() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        //The triangle faces +z, but the file's normals point along -z, the
+        // last of them zero
+        let positions = vec![Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, -1.0, -1.0), Vec3::new(-1.0, 1.0, -1.0)];
+        let flipped = vec![Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, -1.0)];
+        let zero = vec![Vec3::new_zeroes(), Vec3::new_zeroes(), Vec3::new_zeroes()];
+        let r = Ray::new(Vec3::new(-0.5, -0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        for normals in [flipped, zero] {
+            let mesh = TriangleMesh::new(positions.clone(), normals, Vec::new(), vec![[0, 1, 2]], grey());
+
+            //Act
+            let hit = mesh.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng).unwrap();
+
+            //Assert
+            //The normal faces back at the ray, along +z
+            assert!(hit.front_face);
+            assert_eq!(Vec3::new(0.0, 0.0, 1.0), hit.normal);
+        }
+    }
+}
@@ -0,0 +1,467 @@
+pub mod debug;
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::background::{Background, GradientBackground};
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::ScatterSample;
+use crate::pdf::Pdf;
+use crate::random::RenderRng;
+use crate::ray::Ray;
+use crate::vec3::{color::Color, Vec3};
+
+///Finds the colour seen along each ray the camera casts into the world.
+///
+///Integrators are shared between render threads, so must be Send + Sync.
+pub trait Integrator: Send + Sync {
+    ///Returns the colour of the light arriving back along the ray r
+    fn ray_color(self: &Self, r: &Ray, world: &dyn Hittable, rng: &mut RenderRng) -> Color;
+}
+
+///The integrators that can be chosen by name, from the command line or a
+/// scene file: the path tracer, or one of the debug integrators.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntegratorKind {
+    Path,
+    Normals,
+    Depth,
+    Albedo,
+    Uv,
+    ObjectId,
+    BvhCost,
+}
+
+impl IntegratorKind {
+    //Associated constants
+    ///Every kind, in the order they are listed in help and error messages
+    pub const ALL: [IntegratorKind; 7] = [
+        IntegratorKind::Path,
+        IntegratorKind::Normals,
+        IntegratorKind::Depth,
+        IntegratorKind::Albedo,
+        IntegratorKind::Uv,
+        IntegratorKind::ObjectId,
+        IntegratorKind::BvhCost,
+    ];
+
+    ///The number of BVH nodes visited shown as hottest by the BVH cost heatmap
+    pub const BVH_COST_MAX_VISITS: u64 = 256;
+
+    //Methods
+    ///The name the kind is chosen by
+    pub fn name(self: &Self) -> &'static str {
+        match self {
+            IntegratorKind::Path => "path",
+            IntegratorKind::Normals => "normals",
+            IntegratorKind::Depth => "depth",
+            IntegratorKind::Albedo => "albedo",
+            IntegratorKind::Uv => "uv",
+            IntegratorKind::ObjectId => "object_id",
+            IntegratorKind::BvhCost => "bvh_cost",
+        }
+    }
+
+    ///Returns a debug integrator of this kind, or None for the path tracer,
+    /// which the camera configures itself; see Camera::set_integrator.
+    ///
+    ///The depth view is black from depth_far onwards.
+    pub fn debug_integrator(self: &Self, depth_far: f64) -> Option<Arc<dyn Integrator>> {
+        match self {
+            IntegratorKind::Path => None,
+            IntegratorKind::Normals => Some(Arc::new(debug::NormalIntegrator)),
+            IntegratorKind::Depth => Some(Arc::new(debug::DepthIntegrator::new(depth_far))),
+            IntegratorKind::Albedo => Some(Arc::new(debug::AlbedoIntegrator)),
+            IntegratorKind::Uv => Some(Arc::new(debug::UvIntegrator)),
+            IntegratorKind::ObjectId => Some(Arc::new(debug::ObjectIdIntegrator)),
+            IntegratorKind::BvhCost => Some(Arc::new(debug::BvhCostIntegrator::new(IntegratorKind::BVH_COST_MAX_VISITS))),
+        }
+    }
+}
+
+impl fmt::Display for IntegratorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for IntegratorKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        IntegratorKind::ALL.into_iter()
+            .find(|kind| kind.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = IntegratorKind::ALL.iter().map(IntegratorKind::name).collect();
+                format!("unknown integrator `{}`, expected one of {}", name, names.join(", "))
+            })
+    }
+}
+
+///The greatest chance of a path surviving Russian roulette, so that even
+/// paths losing no light, such as between mirrors, end in the end
+const MAX_SURVIVAL: f64 = 0.95;
+
+///Where a ray's direction was sampled from a material's PDF, and its density
+#[derive(Clone, Copy, Debug)]
+struct SampledFrom {
+    origin: Vec3,
+    pdf: f64,
+}
+
+///Traces paths of rays bouncing from the camera out into the world, adding
+/// up the light found along the way.
+///
+///At each hit scattering with a PDF, the lights and background are also
+/// sampled directly, and both ways of finding the same light are weighed
+/// together with multiple importance sampling.
+pub struct PathTracer {
+    max_depth: Option<u32>,     // maximum number of ray bounces; default to none, relying on roulette
+    roulette_min_depth: u32,    // bounces before Russian roulette may end a path; default to 3
+    background: Arc<dyn Background>, // light from rays that miss everything; default to the sky gradient
+    lights: Option<Arc<dyn Hittable>>,  // emitters sampled directly at each scatter; default to none
+}
+
+impl PathTracer {
+    //Constructors
+    pub fn new() -> Self {
+        PathTracer {
+            max_depth: None,
+            roulette_min_depth: 3,
+            background: Arc::new(GradientBackground::sky()),
+            lights: None,
+        }
+    }
+
+    //Methods
+    ///Whether a ray after the given number of bounces is within the maximum depth
+    fn within_max_depth(self: &Self, bounces: u32) -> bool {
+        self.max_depth.is_none_or(|max_depth| bounces < max_depth)
+    }
+
+    ///Returns the light arriving at the hit point straight from a randomly
+    /// chosen light, and from a direction sampled towards the background,
+    /// scattered back along r but for the attenuation.
+    ///
+    ///Shadow rays check nothing blocks the way, letting light through media.
+    /// Each sample is weighed against finding the same light by sampling
+    /// material_pdf, with the power heuristic.
+    fn direct_light(self: &Self, r: &Ray, hit_record: &HitRecord, material_pdf: &dyn Pdf, world: &dyn Hittable, rng: &mut RenderRng) -> Color {
+        let light_along = |shadow_ray: &Ray, ray_t: Interval, light_pdf: f64, radiance: Color, rng: &mut RenderRng| {
+            let scattering_pdf = hit_record.mat.scattering_pdf(r, hit_record, shadow_ray);
+            if scattering_pdf <= 0.0 || radiance == Color::new_zeroes() {
+                return Color::new_zeroes();
+            }
+            let weight = PathTracer::power_heuristic(light_pdf, material_pdf.value(shadow_ray.direction(), rng));
+            let transmittance = world.transmittance(shadow_ray, ray_t, rng);
+            (weight * scattering_pdf / light_pdf) * (radiance * transmittance)
+        };
+
+        let mut color = Color::new_zeroes();
+        if let Some(lights) = self.lights.as_deref() {
+            let direction = lights.random(&hit_record.p, r.time(), rng);
+            let light_pdf = lights.pdf_value(&hit_record.p, &direction, r.time(), rng);
+            let shadow_ray = Ray::new_with_time(hit_record.p, direction, r.time());
+            if light_pdf > 0.0 {
+                //Find the point on the light, then look for anything before it
+                if let Some(light_hit) = lights.hit(&shadow_ray, Interval::new(0.001, f64::INFINITY), rng) {
+                    let radiance = light_hit.mat.emitted(&shadow_ray, &light_hit);
+                    let ray_t = Interval::new(0.001, light_hit.t - 0.001);
+                    color = color + light_along(&shadow_ray, ray_t, light_pdf, radiance, rng);
+                }
+            }
+        }
+
+        if let Some((direction, background_pdf)) = self.background.sample_direction(rng) {
+            let shadow_ray = Ray::new_with_time(hit_record.p, direction, r.time());
+            let radiance = self.background.color(&shadow_ray);
+            let ray_t = Interval::new(0.001, f64::INFINITY);
+            color = color + light_along(&shadow_ray, ray_t, background_pdf, radiance, rng);
+        }
+        color
+    }
+
+    ///Set the maximum number of ray bounces, or None for no limit.
+    ///
+    ///Paths are ended without bias by Russian roulette, so a limit is only
+    /// needed to bound the render time; light from paths cut off is lost.
+    pub fn set_max_depth(self: &mut Self, depth: Option<u32>) {
+        self.max_depth = depth;
+    }
+
+    ///Set the number of bounces before Russian roulette may end a path.
+    ///
+    ///Roulette ends paths at random, more often the less light they carry,
+    /// and boosts those that carry on to make up for it.
+    pub fn set_roulette_min_depth(self: &mut Self, depth: u32) {
+        self.roulette_min_depth = depth;
+    }
+
+    ///Set the background seen by rays that miss every object
+    pub fn set_background(self: &mut Self, background: Arc<dyn Background>) {
+        self.background = background;
+    }
+
+    ///Set the lights, which are sampled directly at every hit scattering with
+    /// a PDF, as well as being found by scattered rays.
+    ///
+    ///Sampling small bright lights directly cuts noise in scenes lit by them.
+    /// The lights must be able to pick directions towards themselves, see
+    /// Hittable::random.
+    pub fn set_lights(self: &mut Self, lights: Arc<dyn Hittable>) {
+        self.lights = Some(lights);
+    }
+
+    //Associated functions
+    ///The power heuristic weight of a sample from a PDF with density f_pdf,
+    /// when another PDF with density g_pdf could also have given it.
+    fn power_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+        let (f2, g2) = (f_pdf * f_pdf, g_pdf * g_pdf);
+        if f2 + g2 > 0.0 { f2 / (f2 + g2) } else { 0.0 }
+    }
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        PathTracer::new()
+    }
+}
+
+impl Integrator for PathTracer {
+    ///Follows the path one bounce at a time, carrying the throughput: the
+    /// fraction of the light along the current ray that reaches the camera.
+    fn ray_color(self: &Self, r: &Ray, world: &dyn Hittable, rng: &mut RenderRng) -> Color {
+        let mut color = Color::new_zeroes();
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = *r;
+        let mut bounces = 0;
+        //Where the current ray was sampled from a PDF, if it was. Light the
+        // ray finds that light sampling could also have found is weighed
+        // against it.
+        let mut sampled: Option<SampledFrom> = None;
+
+        while self.within_max_depth(bounces) {
+            let Some(hit_record) = world.hit(&ray, Interval::new(0.001, f64::INFINITY), rng) else {
+                //Part of the background
+                let weight = match sampled {
+                    Some(sampled) => {
+                        let background_pdf = self.background.pdf_value(&Vec3::unit_vector(ray.direction()));
+                        PathTracer::power_heuristic(sampled.pdf, background_pdf)
+                    }
+                    None => 1.0,
+                };
+                color = color + weight * (throughput * self.background.color(&ray));
+                break;
+            };
+
+            //Part of a hittable, so add the light the material emits, then
+            // follow the light it scatters
+            let emitted = hit_record.mat.emitted(&ray, &hit_record);
+            //Only emitters among the lights could have been sampled directly,
+            // so the light from any other keeps its full weight
+            let color_from_emission = match (sampled, self.lights.as_deref()) {
+                (Some(sampled), Some(lights)) if emitted != Color::new_zeroes() => {
+                    let from_origin = Ray::new_with_time(sampled.origin, *ray.direction(), ray.time());
+                    let is_light = lights.hit(&from_origin, Interval::new(0.001, f64::INFINITY), rng)
+                        .is_some_and(|light_hit| (light_hit.p - hit_record.p).length() < 1e-6);
+                    if is_light {
+                        let light_pdf = lights.pdf_value(&sampled.origin, ray.direction(), ray.time(), rng);
+                        PathTracer::power_heuristic(sampled.pdf, light_pdf) * emitted
+                    } else {
+                        emitted
+                    }
+                }
+                _ => emitted,
+            };
+            color = color + throughput * color_from_emission;
+            let Some(scatter_record) = hit_record.mat.scatter(&ray, &hit_record, rng) else {
+                //No ray returned from scatter, probably because it was
+                // absorbed by the material or it is a light, so no more ray bounces
+                break;
+            };
+            let attenuation = scatter_record.attenuation;
+
+            let weight = match scatter_record.sample {
+                ScatterSample::Specular(scattered) => {
+                    ray = scattered;
+                    sampled = None;
+                    attenuation
+                }
+                ScatterSample::PassThrough(continued) => {
+                    //Not a bounce, so neither counted towards the depth nor
+                    // put to Russian roulette; a medium may be passed through
+                    // at many points along one path
+                    ray = continued;
+                    throughput = throughput * attenuation;
+                    continue;
+                }
+                ScatterSample::Pdf(material_pdf) => {
+                    //Sample the lights directly, unless the path ends here
+                    if self.within_max_depth(bounces + 1) {
+                        let direct = self.direct_light(&ray, &hit_record, &*material_pdf, world, rng);
+                        color = color + throughput * (attenuation * direct);
+                    }
+
+                    //Then sample the material, to find light from everywhere else
+                    let scattered = Ray::new_with_time(hit_record.p, material_pdf.generate(rng), ray.time());
+                    let pdf = material_pdf.value(scattered.direction(), rng);
+                    let scattering_pdf = hit_record.mat.scattering_pdf(&ray, &hit_record, &scattered);
+                    if pdf <= 0.0 || scattering_pdf <= 0.0 {
+                        break;
+                    }
+                    ray = scattered;
+                    sampled = Some(SampledFrom { origin: hit_record.p, pdf });
+                    (scattering_pdf / pdf) * attenuation
+                }
+            };
+            throughput = throughput * weight;
+
+            //Once past the minimum depth, end paths carrying little light at
+            // random, with Russian roulette. Surviving paths carry the light of
+            // those ended, so the mean is unchanged.
+            if bounces >= self.roulette_min_depth {
+                let survival = throughput.r().max(throughput.g()).max(throughput.b()).min(MAX_SURVIVAL);
+                if rng.gen::<f64>() >= survival {
+                    break;
+                }
+                throughput = (1.0 / survival) * throughput;
+            }
+            bounces += 1;
+        }
+        color
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::background::SolidBackground;
+    use crate::hittable::planar::Quad;
+    use crate::hittable::{HittableList, Sphere};
+    use crate::material::{DiffuseLight, Lambertian, Material, Metal};
+    use crate::random::new_rng;
+    use crate::testing::{assert_same_mean_color, grey};
+
+    #[test]
+    fn ray_color_includes_light_emitted_by_material() {
+        //Arrange 
+        let light: Arc<dyn Material> = Arc::new(DiffuseLight::new(Color::new(4.0, 2.0, 1.0)));
+        let world = HittableList::new_with_element(Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -2.0), 1.0, light)));
+        let r = Ray::new(Vec3::new_zeroes(), Vec3::new(0.0, 0.0, -1.0));
+        let mut rng = new_rng(0);
+
+        //Act
+        let result = PathTracer::new().ray_color(&r, &world, &mut rng);
+
+        //Assert
+        assert_eq!((4.0, 2.0, 1.0), (result.r(), result.g(), result.b()));
+    }
+
+    //A tracer seeing no light but from emitters, which gives up after two bounces
+    fn dark_tracer() -> PathTracer {
+        let mut tracer = PathTracer::new();
+        tracer.set_background(Arc::new(SolidBackground::new(Color::new_zeroes())));
+        tracer.set_max_depth(Some(2));
+        tracer
+    }
+
+    //A floor ten units square at y = 0, facing up
+    fn floor(mat: Arc<dyn Material>) -> Arc<dyn Hittable> {
+        Arc::new(Quad::new(Vec3::new(-5.0, 0.0, 5.0), Vec3::new(10.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -10.0), mat))
+    }
+
+    #[test]
+    fn sampling_lights_keeps_the_mean_color() {
+        //Arrange 
+        //A floor lit by a square light overhead, with nothing else
+        let mut rng = new_rng(0);
+        let light: Arc<dyn Material> = Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)));
+        let light_quad: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Vec3::new(-1.0, 2.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0), light));
+        let floors: [Arc<dyn Material>; 2] = [grey(), Arc::new(Metal::new(Color::new(0.5, 0.5, 0.5), 0.5))];
+        let tracer = dark_tracer();
+        let mut lit_tracer = dark_tracer();
+        lit_tracer.set_lights(light_quad.clone());
+        //The mirror reflection of the ray off the floor meets the light
+        let r = Ray::new(Vec3::new(0.0, 1.0, 1.5), Vec3::new(0.0, -1.0, -0.75));
+
+        for mat in floors {
+            let mut world = HittableList::new_with_element(light_quad.clone());
+            world.add(floor(mat));
+
+            //Act and Assert
+            assert_same_mean_color(&tracer, &lit_tracer, &r, &world, &mut rng);
+        }
+    }
+
+    #[test]
+    fn emitters_missing_from_the_lights_keep_their_full_weight() {
+        //Arrange
+        //A floor under an emitter that is not among the lights, which hides
+        // the light above it from the floor entirely
+        let mut rng = new_rng(0);
+        let glow: Arc<dyn Material> = Arc::new(DiffuseLight::new(Color::new(1.0, 1.0, 1.0)));
+        let light: Arc<dyn Material> = Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)));
+        let light_quad: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Vec3::new(-1.0, 2.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0), light));
+        let mut world = HittableList::new_with_element(light_quad.clone());
+        world.add(Arc::new(Quad::new(
+            Vec3::new(-2.0, 1.0, -2.0), Vec3::new(4.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 4.0), glow)));
+        world.add(floor(grey()));
+        let tracer = dark_tracer();
+        let mut lit_tracer = dark_tracer();
+        lit_tracer.set_lights(light_quad);
+        let r = Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, -1.0, 0.0));
+
+        //Act and Assert
+        assert_same_mean_color(&tracer, &lit_tracer, &r, &world, &mut rng);
+    }
+
+    #[test]
+    fn russian_roulette_keeps_the_mean_color() {
+        //Arrange
+        //Two grey planes facing each other under a white sky, so light 
+        // bounces back and forth many times before escaping
+        let mut rng = new_rng(0);
+        let grey: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.7, 0.7, 0.7)));
+        let mut world = HittableList::new_with_element(floor(grey.clone()));
+        world.add(Arc::new(Quad::new(
+            Vec3::new(-5.0, 1.0, -5.0), Vec3::new(10.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 10.0), grey)));
+        let mut tracer = PathTracer::new();
+        tracer.set_max_depth(Some(40));
+        tracer.set_roulette_min_depth(40);
+        let mut roulette_tracer = PathTracer::new();
+        roulette_tracer.set_roulette_min_depth(0);
+        let r = Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, -1.0, 0.2));
+
+        //Act and Assert
+        assert_same_mean_color(&tracer, &roulette_tracer, &r, &world, &mut rng);
+    }
+
+    #[test]
+    fn long_paths_do_not_overflow_the_stack() {
+        //Arrange
+        //A ray bouncing straight up and down between two perfect mirrors
+        // never loses light, so only the maximum depth ends it
+        let mut rng = new_rng(0);
+        let mirror: Arc<dyn Material> = Arc::new(Metal::new(Color::new(1.0, 1.0, 1.0), 0.0));
+        let mut world = HittableList::new_with_element(floor(mirror.clone()));
+        world.add(Arc::new(Quad::new(
+            Vec3::new(-5.0, 1.0, -5.0), Vec3::new(10.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 10.0), mirror)));
+        let mut tracer = PathTracer::new();
+        tracer.set_max_depth(Some(1_000_000));
+        tracer.set_roulette_min_depth(1_000_000);
+        let r = Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, -1.0, 0.0));
+
+        //Act
+        let result = tracer.ray_color(&r, &world, &mut rng);
+
+        //Assert
+        assert_eq!(Color::new_zeroes(), result);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::hittable::bvh::count_node_visits;
+use crate::hittable::{HitRecord, Hittable};
+use crate::integrator::Integrator;
+use crate::interval::Interval;
+use crate::random::RenderRng;
+use crate::ray::Ray;
+use crate::vec3::color::Color;
+
+//Debug integrators show what the first hit along each ray reports, rather
+// than the light arriving along it. Rays that miss everything are black.
+
+//The first hit along the ray, as the path tracer finds it
+fn first_hit(r: &Ray, world: &dyn Hittable, rng: &mut RenderRng) -> Option<HitRecord> {
+    world.hit(r, Interval::new(0.001, f64::INFINITY), rng)
+}
+
+///Shows the normal at the first hit, which faces back along the ray, with
+/// each component mapped from [-1, 1] to a channel in [0, 1].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NormalIntegrator;
+
+impl Integrator for NormalIntegrator {
+    fn ray_color(self: &Self, r: &Ray, world: &dyn Hittable, rng: &mut RenderRng) -> Color {
+        match first_hit(r, world, rng) {
+            Some(hit_record) => {
+                let n = hit_record.normal;
+                Color::new(0.5 * (n.x + 1.0), 0.5 * (n.y + 1.0), 0.5 * (n.z + 1.0))
+            }
+            None => Color::new_zeroes(),
+        }
+    }
+}
+
+///Shows the distance to the first hit, fading linearly from white at the
+/// ray's origin to black at the far distance and beyond.
+#[derive(Clone, Copy, Debug)]
+pub struct DepthIntegrator {
+    far: f64,
+}
+
+impl DepthIntegrator {
+    //Constructors
+    ///Create the depth view, which is black from far onwards.
+    ///
+    /// # Panics
+    /// If far is not greater than 0.
+    pub fn new(far: f64) -> Self {
+        assert!(far > 0.0, "far must be greater than 0");
+        DepthIntegrator { far }
+    }
+}
+
+impl Integrator for DepthIntegrator {
+    fn ray_color(self: &Self, r: &Ray, world: &dyn Hittable, rng: &mut RenderRng) -> Color {
+        let Some(hit_record) = first_hit(r, world, rng) else {
+            return Color::new_zeroes();
+        };
+
+        let distance = hit_record.t * r.direction().length();
+        let shade = (1.0 - distance / self.far).clamp(0.0, 1.0);
+        Color::new(shade, shade, shade)
+    }
+}
+
+///Shows the colour of the first hit's material before any lighting: the
+/// attenuation of the light it scatters. Materials that scatter nothing,
+/// such as lights, are black.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AlbedoIntegrator;
+
+impl Integrator for AlbedoIntegrator {
+    fn ray_color(self: &Self, r: &Ray, world: &dyn Hittable, rng: &mut RenderRng) -> Color {
+        first_hit(r, world, rng)
+            .and_then(|hit_record| hit_record.mat.scatter(r, &hit_record, rng))
+            .map_or(Color::new_zeroes(), |scatter_record| scatter_record.attenuation)
+    }
+}
+
+///Shows the surface coordinates (u, v) of the first hit as red and green
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UvIntegrator;
+
+impl Integrator for UvIntegrator {
+    fn ray_color(self: &Self, r: &Ray, world: &dyn Hittable, rng: &mut RenderRng) -> Color {
+        match first_hit(r, world, rng) {
+            Some(hit_record) => Color::new(hit_record.u, hit_record.v, 0.0),
+            None => Color::new_zeroes(),
+        }
+    }
+}
+
+///Shows each object in its own colour, picked at random from its id; see
+/// HitRecord::object_id. Every triangle of a mesh is an object of its own.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ObjectIdIntegrator;
+
+impl ObjectIdIntegrator {
+    //Associated functions
+    ///Scramble the bits of the id, so that objects with similar ids get
+    /// quite different colours
+    fn id_color(id: usize) -> Color {
+        //The SplitMix64 finaliser
+        let mut x = id as u64;
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+        x ^= x >> 31;
+        let channel = |shift: u32| ((x >> shift) & 0xff) as f64 / 255.0;
+        Color::new(channel(0), channel(8), channel(16))
+    }
+}
+
+impl Integrator for ObjectIdIntegrator {
+    fn ray_color(self: &Self, r: &Ray, world: &dyn Hittable, rng: &mut RenderRng) -> Color {
+        match first_hit(r, world, rng) {
+            Some(hit_record) => ObjectIdIntegrator::id_color(hit_record.object_id),
+            None => Color::new_zeroes(),
+        }
+    }
+}
+
+///Shows the number of BVH nodes visited looking for the first hit, as a
+/// heatmap running from blue for none, through green, to red for max_visits
+/// or more. The scale is logarithmic, so that both small scenes and those
+/// with deep hierarchies show detail. Rays that miss everything show their
+/// cost too.
+#[derive(Clone, Copy, Debug)]
+pub struct BvhCostIntegrator {
+    max_visits: u64,
+}
+
+impl BvhCostIntegrator {
+    //Constructors
+    ///Create the heatmap, which is red at max_visits nodes visited.
+    ///
+    /// # Panics
+    /// If max_visits is 0.
+    pub fn new(max_visits: u64) -> Self {
+        assert!(max_visits > 0, "max_visits must be at least 1");
+        BvhCostIntegrator { max_visits }
+    }
+
+    //Associated functions
+    ///The heatmap colour for a fraction t in [0, 1] of the way from cold to hot
+    fn heat(t: f64) -> Color {
+        if t < 0.5 {
+            let s = 2.0 * t;
+            Color::new(0.0, s, 1.0 - s)
+        } else {
+            let s = 2.0 * t - 1.0;
+            Color::new(s, 1.0 - s, 0.0)
+        }
+    }
+}
+
+impl Integrator for BvhCostIntegrator {
+    fn ray_color(self: &Self, r: &Ray, world: &dyn Hittable, rng: &mut RenderRng) -> Color {
+        let (_, visits) = count_node_visits(|| first_hit(r, world, rng));
+        let t = (visits as f64).ln_1p() / (self.max_visits as f64).ln_1p();
+        BvhCostIntegrator::heat(t.min(1.0))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::hittable::bvh::BvhNode;
+    use crate::hittable::{HittableList, Sphere};
+    use crate::material::{Lambertian, Material};
+    use crate::random::new_rng;
+    use crate::vec3::Vec3;
+
+    //Two spheres side by side, 5 units in front of the origin
+    fn two_spheres() -> BvhNode {
+        let mut list = HittableList::new_empty();
+        let red: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.8, 0.1, 0.1)));
+        let blue: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.1, 0.1, 0.8)));
+        list.add(Arc::new(Sphere::new(Vec3::new(-1.0, 0.0, -5.0), 1.0, red)));
+        list.add(Arc::new(Sphere::new(Vec3::new(1.0, 0.0, -5.0), 1.0, blue)));
+        BvhNode::new(list)
+    }
+
+    #[test]
+    fn debug_integrators_show_what_the_first_hit_reports() {
+        //Arrange
+        let mut rng = new_rng(0);
+        let world = two_spheres();
+        let left = Ray::new(Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let right = Ray::new(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let miss = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        //Act
+        let normal = NormalIntegrator.ray_color(&left, &world, &mut rng);
+        let depth = DepthIntegrator::new(8.0).ray_color(&left, &world, &mut rng);
+        let beyond = DepthIntegrator::new(2.0).ray_color(&left, &world, &mut rng);
+        let albedo = AlbedoIntegrator.ray_color(&right, &world, &mut rng);
+        let left_id = ObjectIdIntegrator.ray_color(&left, &world, &mut rng);
+        let right_id = ObjectIdIntegrator.ray_color(&right, &world, &mut rng);
+        let missed = NormalIntegrator.ray_color(&miss, &world, &mut rng);
+
+        //Assert
+        //The normal faces straight back at the ray, along +z
+        assert_eq!(Color::new(0.5, 0.5, 1.0), normal);
+        //The hit is 4 units away, halfway to the far distance or past it
+        assert!((depth.r() - 0.5).abs() < 1e-9, "depth {}", depth.r());
+        assert_eq!(Color::new_zeroes(), beyond);
+        assert_eq!(Color::new(0.1, 0.1, 0.8), albedo);
+        assert_ne!(left_id, right_id);
+        assert_eq!(Color::new_zeroes(), missed);
+    }
+
+    #[test]
+    fn bvh_cost_is_hotter_for_rays_visiting_more_nodes() {
+        //Arrange
+        //Four spheres in a row make a root over two nodes of two spheres each
+        let mut rng = new_rng(0);
+        let mat: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut list = HittableList::new_empty();
+        for x in 0..4 {
+            list.add(Arc::new(Sphere::new(Vec3::new(3.0 * x as f64, 0.0, -5.0), 1.0, mat.clone())));
+        }
+        let world = BvhNode::new(list);
+        let integrator = BvhCostIntegrator::new(3);
+        let hit = Ray::new(Vec3::new_zeroes(), Vec3::new(0.0, 0.0, -1.0));
+        let miss = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        //Act
+        let hot = integrator.ray_color(&hit, &world, &mut rng);
+        let cold = integrator.ray_color(&miss, &world, &mut rng);
+
+        //Assert
+        //The hit visits all three nodes, the miss only the root, which is
+        // halfway on the log scale
+        assert_eq!(Color::new(1.0, 0.0, 0.0), hot);
+        assert!(cold.r() == 0.0 && cold.g() > cold.b(), "{:?}", cold);
+    }
+}
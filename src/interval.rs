@@ -16,6 +16,14 @@ impl Interval {
         }
     }
 
+    ///Create the tightest interval enclosing both input intervals
+    pub fn new_enclosing(a: &Interval, b: &Interval) -> Self {
+        Interval {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        }
+    }
+
     pub const fn new_empty() -> Self {
         Interval {
             min: f64::INFINITY,
@@ -46,6 +54,12 @@ impl Interval {
         }
     }
 
+    ///Returns a new interval padded by delta/2 on each side
+    pub fn expand(self: &Self, delta: f64) -> Interval {
+        let padding = delta / 2.0;
+        Interval::new(self.min - padding, self.max + padding)
+    }
+
     //Associated constants
     pub const EMPTY: Self = Self::new_empty(); 
     pub const UNIVERSE: Self = Self{
@@ -1,24 +1,38 @@
 pub mod vec3; //includes the sub-module color 
 pub mod ray; 
-pub mod hittable; 
+pub mod hittable; //includes the sub-modules bvh, instance, medium, planar and triangle
 pub mod interval; 
+pub mod aabb;
+pub mod transform;
 pub mod camera;
+pub mod integrator; //includes the sub-module debug
+pub mod background;
+pub mod distribution;
 pub mod material;
+pub mod texture; //includes the sub-module perlin
+pub mod random;
+pub mod onb;
+pub mod pdf;
+pub mod density;
+pub mod obj;
+pub mod scene;
+#[cfg(test)]
+mod testing; //fixtures shared between unit tests
 
 use image::{ImageBuffer, Rgb};
 
 use camera::Camera;
-use hittable::HittableList;
+use hittable::Hittable;
 
 ///Render an image onto the supplied ImageBuffer  
 pub fn render(img_buf: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, 
-    world: &HittableList, 
+    world: &dyn Hittable, 
     samples_per_pixel: u32, 
     max_depth: u32) { 
   //Camera: renders an image of the world onto the ImageBuffer
   let mut cam = Camera::new(img_buf.width() as f64, img_buf.height() as f64);
   cam.set_samples_per_pixel(samples_per_pixel);
-  cam.set_max_depth(max_depth);
+  cam.set_max_depth(Some(max_depth));
   cam.render(img_buf, world);
 
 }
\ No newline at end of file
@@ -1,54 +1,136 @@
-use std::rc::Rc;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::process::ExitCode;
 
-use rtiow::hittable::{Sphere, HittableList};
-use rtiow::material::{Lambertian, Material, Metal, Dielectric};
-use rtiow::vec3::color::Color;
-use rtiow::vec3::Vec3;
+use clap::Parser;
+use image::ImageFormat;
+
+use rtiow::hittable::bvh::BvhNode;
+use rtiow::integrator::IntegratorKind;
+use rtiow::scene::load_scene;
+
+///Render a ray traced image of a scene.
+///
+///Render settings given on the command line override those in the scene file.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+  ///Scene description file (TOML)
+  #[arg(short, long, default_value = "scenes/default.toml")]
+  scene: PathBuf,
+
+  ///Output image file; its extension chooses the image format
+  #[arg(short, long, default_value = "image.png")]
+  output: PathBuf,
+
+  ///Image width in pixels. If only the width is given the scene's aspect ratio is kept
+  #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+  width: Option<u32>,
+
+  ///Image height in pixels. If only the height is given the scene's aspect ratio is kept
+  #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+  height: Option<u32>,
+
+  ///Number of ray samples per pixel
+  #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+  samples: Option<u32>,
+
+  ///Maximum number of ray bounces
+  #[arg(long)]
+  max_depth: Option<u32>,
+
+  ///Number of ray bounces before Russian roulette may end a path
+  #[arg(long)]
+  roulette_min_depth: Option<u32>,
+
+  ///Seed for the random sampling; renders with the same seed are identical
+  #[arg(long)]
+  seed: Option<u64>,
+
+  ///Number of render threads, 0 for one per CPU core
+  #[arg(long)]
+  threads: Option<usize>,
+
+  ///Integrator: "path" to trace the light, or a debug view of the first hit along each 
+  /// ray: "normals", "depth", "albedo", "uv", "object_id" or "bvh_cost"
+  #[arg(long)]
+  integrator: Option<IntegratorKind>,
+
+  ///Distance at which the depth integrator fades to black
+  #[arg(long)]
+  depth_far: Option<f64>,
+}
 
 // Handle configuration logic: 
-//  create an appropriately sized ImageBuffer;
-//  set up the world being imaged
-//  set the number of ray saamples per pixel
+//  read the command line arguments;
+//  load the scene description, which sets up the world being imaged,
+//  positions the camera and sets the number of ray samples per pixel;
+//  create an appropriately sized ImageBuffer.
 // Call the library code to carry out the program logic (generating a ray traced image).
-// Handle errors.
-fn main() {
-  // Configure the ImageBuffer size using the desired aspect ratio and width
-  let aspect_ratio = 16.0 / 9.0; 
-  let image_width = 400;
-  //Calculate image_height ensuring it is >=1
-  let height = image_width as f64 / aspect_ratio;
-  let image_height = if height < 1.0 { 1 } else { height as u32}; 
+// Handle errors, by printing a message and exiting with a failure code.
+fn main() -> ExitCode {
+  let args = Args::parse();
+  match run(&args) {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(err) => {
+      eprintln!("error: {}", err);
+      ExitCode::FAILURE
+    }
+  }
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+  let mut scene = load_scene(&args.scene)?;
+
+  //Apply the command line overrides
+  let (image_width, image_height) = match (args.width, args.height) {
+    (Some(width), Some(height)) => (width, height),
+    (Some(width), None) => (width, scale(scene.image_height, width, scene.image_width)),
+    (None, Some(height)) => (scale(scene.image_width, height, scene.image_height), height),
+    (None, None) => (scene.image_width, scene.image_height),
+  };
+  let camera = &mut scene.camera;
+  camera.set_image_size(image_width as f64, image_height as f64);
+  if let Some(samples) = args.samples { camera.set_samples_per_pixel(samples); }
+  if let Some(max_depth) = args.max_depth { camera.set_max_depth(Some(max_depth)); }
+  if let Some(depth) = args.roulette_min_depth { camera.set_roulette_min_depth(depth); }
+  if let Some(seed) = args.seed { camera.set_seed(seed); }
+  if let Some(threads) = args.threads { camera.set_threads(threads); }
+  if args.integrator.is_some() || args.depth_far.is_some() {
+    let depth_far = args.depth_far.unwrap_or(scene.depth_far);
+    if !(depth_far.is_finite() && depth_far > 0.0) {
+      return Err("--depth-far must be greater than 0".into());
+    }
+    camera.set_integrator(args.integrator.unwrap_or(scene.integrator).debug_integrator(depth_far));
+  }
+
+  //Check the output can be written before spending time on the render
+  let format = ImageFormat::from_path(&args.output)
+    .map_err(|err| format!("cannot write {}: {}", args.output.display(), err))?;
+  let file = File::create(&args.output)
+    .map_err(|err| format!("cannot write {}: {}", args.output.display(), err))?;
 
   //Create the ImageBuffer; 
   //  Note: could use the type alias RgbImage for ImageBuffer<image::Rgb<u8>, Vec<u8>>, 
   //  see https://docs.rs/image/latest/image/type.RgbImage.html
   let mut image_buffer  = image::ImageBuffer::new(image_width, image_height);
 
-  //Create the Materials 
-  // We must specify the type to get dynamic allocation. Leaving this to type inference
-  // would incorrectly infer a type of Rc<Lambertian>, Rc<Metal>, etc. 
-  let material_ground: Rc<dyn Material>  = Rc::new(Lambertian{albedo: Color::new(0.8, 0.8, 0.0)});
-  let material_center: Rc<dyn Material> = Rc::new(Lambertian{albedo: Color::new(0.1, 0.2, 0.5)});
-    let material_left: Rc<dyn Material> = Rc::new(Dielectric{refraction_index: 1.5});         //Refactor
-  let material_bubble: Rc<dyn Material> = Rc::new(Dielectric{refraction_index: 1.0 / 1.5}); //Add
-  let material_right: Rc<dyn Material> = Rc::new(Metal{albedo: Color::new(0.8, 0.6, 0.2), fuzz: 0.1});
-
-  //Create the World: we must place hittable objects into the scene  
-  let mut world: HittableList = HittableList::new_empty();
-  world.add(Rc::new(Sphere::new(Vec3::new(0.0, -100.5, -1.0), 100.0, material_ground.clone())));
-  world.add(Rc::new(Sphere::new(Vec3::new(0.0, 0.0, -1.2), 0.5, material_center.clone())));
-  world.add(Rc::new(Sphere::new(Vec3::new(-1.0, 0.0, -1.0), 0.5, material_left.clone())));
-  world.add(Rc::new(Sphere::new(Vec3::new(-1.0, 0.0, -1.0), 0.4, material_bubble.clone())));
-  world.add(Rc::new(Sphere::new(Vec3::new(1.0, 0.0, -1.0), 0.5, material_right.clone())));
-  
-  // eprint!("Starting render\n");
-  rtiow::render(&mut image_buffer, &world, 50, 10); 
+  //Put the world's objects into a BVH so each ray only tests the objects near it
+  let world = BvhNode::new(scene.world);
+
+  scene.camera.render(&mut image_buffer, &world); 
   
-  // Write the ImageBuffer to a file
-  //  We can ignore errors for now so just "unwrap" the Ok result.
-  //  If there is an Err result then thread 'main' will panic.   
-  image_buffer.save("image.png").unwrap();
+  // Write the ImageBuffer to the file
+  image_buffer.write_to(&mut BufWriter::new(file), format)
+    .map_err(|err| format!("cannot write {}: {}", args.output.display(), err))?;
 
-  // eprint!("\rDone                    ");
+  Ok(())
+}
 
+//Scale a length by the ratio numerator/denominator, ensuring it is >=1
+fn scale(length: u32, numerator: u32, denominator: u32) -> u32 {
+  let scaled = length as f64 * numerator as f64 / denominator as f64;
+  if scaled < 1.0 { 1 } else { scaled.round() as u32 }
 }
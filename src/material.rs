@@ -1,53 +1,107 @@
+use std::f64::consts::PI;
 use std::fmt::Debug;
+use std::sync::Arc;
+
+use rand::Rng;
 
 use crate::hittable::HitRecord;
+use crate::pdf::{CosinePdf, FuzzyReflectionPdf, HenyeyGreensteinPdf, Pdf, SpherePdf};
+use crate::random::RenderRng;
 use crate::ray::Ray;
+use crate::texture::{SolidColor, Texture};
 use crate::vec3::{Vec3, color::Color};
 
 
-pub trait Material: Debug {
-    fn scatter(self: &Self, _r_in: &Ray, _hit_record: &HitRecord) -> Option<(Color, Ray)> {
+///How a material scatters an incoming ray: the attenuation of the light and
+/// the way the scattered direction is chosen.
+pub struct ScatterRecord {
+    pub attenuation: Color,
+    pub sample: ScatterSample,
+}
+
+///The way a material chooses the direction to scatter in
+pub enum ScatterSample {
+    ///A direction from the PDF. The camera also samples the lights directly,
+    /// weighing light found each way against the other by the PDF and 
+    /// scattering_pdf. 
+    Pdf(Box<dyn Pdf>),
+    ///Exactly this ray, such as a perfect mirror reflection, whose direction 
+    /// has no density to weigh against others. The attenuation alone weights
+    /// it, and the lights are not sampled.
+    Specular(Ray),
+    ///This ray, carrying on in the incoming direction, such as through a
+    /// medium. Light it finds is weighed as if found by the last scatter
+    /// before it, and it does not count as a bounce.
+    PassThrough(Ray),
+}
+
+///Materials are shared between render threads, so must be Send + Sync.
+pub trait Material: Debug + Send + Sync {
+    ///Returns how the material scatters the incoming ray, or None if it 
+    /// absorbs it. 
+    fn scatter(self: &Self, _r_in: &Ray, _hit_record: &HitRecord, _rng: &mut RenderRng) -> Option<ScatterRecord> {
         None
     }
+
+    ///The density, per unit solid angle, of light arriving along r_in being
+    /// scattered along scattered. 
+    /// 
+    ///Only needed by materials scattering with ScatterSample::Pdf; the 
+    /// attenuation times this is the material's BSDF, or phase function.
+    fn scattering_pdf(self: &Self, _r_in: &Ray, _hit_record: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    ///The light given off by the material at the hit point. 
+    /// 
+    ///Most materials do not emit light, so the default is black.
+    fn emitted(self: &Self, _r_in: &Ray, _hit_record: &HitRecord) -> Color {
+        Color::new_zeroes()
+    }
 }
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
+        Lambertian::new_textured(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn new_textured(albedo: Arc<dyn Texture>) -> Self {
         Lambertian { albedo } 
     }
 }
 
 impl Material for Lambertian {
     ///Lambertian materials scatter incoming rays randomly about the outward 
-    /// facing normal of the incoming ray's hit point.
-    /// 
-    
-    // The incoming ray is not actually needed for this material, but is for others. 
-    fn scatter(self: &Self, _r_in: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray)> { 
-        let scatter_direction = hit_record.normal + Vec3::random_unit_vector();
-        let scatter_direction
-            = if scatter_direction.near_zero() { 
-                hit_record.normal
-            } else {
-                scatter_direction
-            };
-        let scattered = Ray::new(hit_record.p, scatter_direction);
-        Some((self.albedo, scattered))   
+    /// facing normal of the incoming ray's hit point, in proportion to the 
+    /// cosine of the angle to the normal.
+    fn scatter(self: &Self, _r_in: &Ray, hit_record: &HitRecord, _rng: &mut RenderRng) -> Option<ScatterRecord> { 
+        let attenuation = self.albedo.value(hit_record.u, hit_record.v, &hit_record.p);
+        let sample = ScatterSample::Pdf(Box::new(CosinePdf::new(&hit_record.normal)));
+        Some(ScatterRecord { attenuation, sample })
+    }
+
+    fn scattering_pdf(self: &Self, _r_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> f64 {
+        let cos_theta = Vec3::dot(&hit_record.normal, &Vec3::unit_vector(scattered.direction()));
+        (cos_theta / PI).max(0.0)
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct Metal {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture>,
     pub fuzz: f64,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Self {
+        Metal::new_textured(Arc::new(SolidColor::new(albedo)), fuzz)
+    }
+
+    pub fn new_textured(albedo: Arc<dyn Texture>, fuzz: f64) -> Self {
         let fuzz_factor = if fuzz < 1.0 {fuzz} else {1.0};
         Metal { 
             albedo,
@@ -56,18 +110,35 @@ impl Metal {
     }
 }
 
+impl Metal {
+    //Methods
+    ///The unit mirror reflection of the incoming ray
+    fn reflected(self: &Self, r_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        Vec3::unit_vector(&Vec3::reflect(r_in.direction(), &hit_record.normal))
+    }
+}
+
 impl Material for Metal {
-    ///Metal materials reflect the incoming rays about the hit point normal
-    fn scatter(self: &Self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray)> {
-        let reflected = Vec3::reflect(r_in.direction(), &hit_record.normal);
-        let reflected = Vec3::unit_vector(&reflected) + (self.fuzz * Vec3::random_unit_vector());
-        let fuzzed_reflection = Ray::new(hit_record.p, reflected);
-        if Vec3::dot(&fuzzed_reflection.direction(), &hit_record.normal) > 0.0 {
-            Some((self.albedo, fuzzed_reflection))
+    ///Metal materials reflect the incoming rays about the hit point normal.
+    /// Polished metal is a perfect mirror; rough metal scatters about the 
+    /// mirror reflection, absorbing rays nudged into the object.
+    fn scatter(self: &Self, r_in: &Ray, hit_record: &HitRecord, _rng: &mut RenderRng) -> Option<ScatterRecord> {
+        let reflected = self.reflected(r_in, hit_record);
+        let attenuation = self.albedo.value(hit_record.u, hit_record.v, &hit_record.p);
+        let sample = if self.fuzz > 0.0 {
+            ScatterSample::Pdf(Box::new(FuzzyReflectionPdf::new(reflected, self.fuzz)))
         } else {
+            ScatterSample::Specular(Ray::new_with_time(hit_record.p, reflected, r_in.time()))
+        };
+        Some(ScatterRecord { attenuation, sample })
+    }
+
+    fn scattering_pdf(self: &Self, r_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> f64 {
+        if self.fuzz <= 0.0 || Vec3::dot(scattered.direction(), &hit_record.normal) <= 0.0 {
             //Fuzzed reflected ray points into the object
-            None
-        }        
+            return 0.0;
+        }
+        FuzzyReflectionPdf::density(&self.reflected(r_in, hit_record), self.fuzz, scattered.direction())
     }
 }
 
@@ -92,7 +163,7 @@ impl Dielectric {
 
 impl Material for Dielectric {
     //The sphere will always refraact, so will look odd
-    fn scatter(self: &Self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(self: &Self, r_in: &Ray, hit_record: &HitRecord, rng: &mut RenderRng) -> Option<ScatterRecord> {
         //Refractive index is different depending on whether the 
         // ray is entering or exiting the material 
         let ri = if hit_record.front_face {
@@ -107,7 +178,7 @@ impl Material for Dielectric {
         let cannot_refract = ri * sin_theta > 1.0; 
 
         //Randomly reflect rays 
-        let randomly_reflected = Dielectric::reflectance(cos_theta, ri) > rand::random::<f64>();
+        let randomly_reflected = Dielectric::reflectance(cos_theta, ri) > rng.gen::<f64>();
 
         let direction = if cannot_refract || randomly_reflected {
             Vec3::reflect(&unit_direction, &hit_record.normal)
@@ -115,6 +186,147 @@ impl Material for Dielectric {
             Vec3::refract(&unit_direction, &hit_record.normal, ri)
         };
 
-        Some((Color::new(1.0, 1.0, 1.0), Ray::new(hit_record.p, direction)))
+        let scattered = Ray::new_with_time(hit_record.p, direction, r_in.time());
+        Some(ScatterRecord { attenuation: Color::new(1.0, 1.0, 1.0), sample: ScatterSample::Specular(scattered) })
+    }
+}
+
+///The phase function of a participating medium, such as fog or smoke, which
+/// scatters light equally in every direction.
+#[derive(Clone, Debug)]
+pub struct Isotropic {
+    pub albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Isotropic::new_textured(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn new_textured(albedo: Arc<dyn Texture>) -> Self {
+        Isotropic { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(self: &Self, _r_in: &Ray, hit_record: &HitRecord, _rng: &mut RenderRng) -> Option<ScatterRecord> {
+        let attenuation = self.albedo.value(hit_record.u, hit_record.v, &hit_record.p);
+        Some(ScatterRecord { attenuation, sample: ScatterSample::Pdf(Box::new(SpherePdf::new())) })
     }
-} 
\ No newline at end of file
+
+    fn scattering_pdf(self: &Self, _r_in: &Ray, _hit_record: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+///The Henyey–Greenstein phase function, for participating media that scatter
+/// light unevenly, such as cloud (forwards) or dust (backwards).
+///
+///The anisotropy g, between -1 and 1, is the mean cosine of the scattering
+/// angle: positive values scatter forwards, negative values backwards, and 0
+/// scatters equally in every direction like Isotropic.
+#[derive(Clone, Debug)]
+pub struct HenyeyGreenstein {
+    pub albedo: Arc<dyn Texture>,
+    pub g: f64,
+}
+
+impl HenyeyGreenstein {
+    //Constructors
+    pub fn new(albedo: Color, g: f64) -> Self {
+        HenyeyGreenstein::new_textured(Arc::new(SolidColor::new(albedo)), g)
+    }
+
+    pub fn new_textured(albedo: Arc<dyn Texture>, g: f64) -> Self {
+        //Keep away from +-1, where the distribution becomes a delta function
+        HenyeyGreenstein { albedo, g: g.clamp(-0.999, 0.999) }
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    ///Scatters about the incoming ray's direction of travel
+    fn scatter(self: &Self, r_in: &Ray, hit_record: &HitRecord, _rng: &mut RenderRng) -> Option<ScatterRecord> {
+        let attenuation = self.albedo.value(hit_record.u, hit_record.v, &hit_record.p);
+        let sample = ScatterSample::Pdf(Box::new(HenyeyGreensteinPdf::new(r_in.direction(), self.g)));
+        Some(ScatterRecord { attenuation, sample })
+    }
+
+    fn scattering_pdf(self: &Self, r_in: &Ray, _hit_record: &HitRecord, scattered: &Ray) -> f64 {
+        let cos_theta = Vec3::dot(&Vec3::unit_vector(r_in.direction()), &Vec3::unit_vector(scattered.direction()));
+        HenyeyGreensteinPdf::phase(self.g, cos_theta)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        DiffuseLight { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    ///Diffuse lights emit the same light in every direction and do not 
+    /// scatter incoming rays. 
+    /// 
+    ///Emitted values above 1 are allowed, so a small light can brighten a scene.
+    fn emitted(self: &Self, _r_in: &Ray, _hit_record: &HitRecord) -> Color {
+        self.emit
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn henyey_greenstein_mean_cosine_is_anisotropy() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let phase = HenyeyGreenstein::new(Color::new(1.0, 1.0, 1.0), 0.6);
+        let r_in = Ray::new(Vec3::new_zeroes(), Vec3::new(0.0, 0.0, -2.0));
+        let mat: Arc<dyn Material> = Arc::new(phase.clone());
+        let hit_record = HitRecord::new(Vec3::new_zeroes(), Vec3::new(1.0, 0.0, 0.0), mat, 1.0, true);
+        let samples = 20000;
+
+        //Act
+        let mut sum = 0.0;
+        for _ in 0..samples {
+            let Some(ScatterRecord { sample: ScatterSample::Pdf(pdf), .. }) = phase.scatter(&r_in, &hit_record, &mut rng) else {
+                panic!("expected a phase function PDF");
+            };
+            sum += Vec3::dot(&Vec3::unit_vector(&pdf.generate(&mut rng)), &Vec3::new(0.0, 0.0, -1.0));
+        }
+
+        //Assert
+        let mean_cosine = sum / samples as f64;
+        assert!((mean_cosine - 0.6).abs() < 0.02, "mean cosine was {}", mean_cosine);
+    }
+
+    #[test]
+    fn only_rough_metal_and_diffuse_materials_sample_a_pdf() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let r_in = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let materials: [(Arc<dyn Material>, bool); 4] = [
+            (Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.0)), false),
+            (Arc::new(Dielectric::new(1.5)), false),
+            (Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.3)), true),
+            (Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))), true),
+        ];
+
+        for (mat, expected) in materials {
+            let hit_record = HitRecord::new(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), mat.clone(), 1.0, true);
+
+            //Act
+            let result = mat.scatter(&r_in, &hit_record, &mut rng).unwrap();
+
+            //Assert
+            assert_eq!(expected, matches!(result.sample, ScatterSample::Pdf(_)), "{:?}", mat);
+        }
+    }
+}
@@ -0,0 +1,475 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::hittable::triangle::TriangleMesh;
+use crate::hittable::HittableList;
+use crate::material::{Dielectric, Lambertian, Material, Metal};
+use crate::vec3::{color::Color, Vec3};
+
+///An error loading a Wavefront .obj file or one of its .mtl material libraries.
+#[derive(Debug)]
+pub enum ObjError {
+    ///The file could not be read
+    Io { file: PathBuf, source: io::Error },
+    ///The file contents are invalid. Lines are numbered from 1.
+    Parse { file: PathBuf, line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { file, source } => write!(f, "{}: {}", file.display(), source),
+            ObjError::Parse { file, line, message } => write!(f, "{}:{}: {}", file.display(), line, message),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+///Load a Wavefront .obj file, and any .mtl files it references, into a list of
+/// triangle meshes.
+///
+///Each group (`g` or `o`) and material (`usemtl`) combination becomes one
+/// TriangleMesh. Polygons are triangulated as fans around their first vertex.
+/// Faces with no material are given a mid-grey Lambertian material.
+///
+///MTL materials map onto the existing materials:
+/// - transparent (`d` < 1, or `illum` 4, 6, 7 or 9) becomes Dielectric with refraction index `Ni`
+/// - reflective (`illum` 3, 5 or 8) becomes Metal with albedo `Ks` and a fuzz derived from `Ns`
+/// - anything else becomes Lambertian with albedo `Kd`
+pub fn load_obj(path: impl AsRef<Path>) -> Result<HittableList, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+    let mut parser = ObjParser::new(path);
+
+    for (index, line) in source.lines().enumerate() {
+        parser.parse_line(line, index + 1)?;
+    }
+
+    Ok(parser.finish())
+}
+
+///Load the materials from a Wavefront .mtl file, keyed by name
+pub fn load_mtl(path: impl AsRef<Path>) -> Result<HashMap<String, Arc<dyn Material>>, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let mut tokens = Tokens::new(line, path, line_number);
+        let Some(keyword) = tokens.next() else { continue };
+
+        if keyword == "newmtl" {
+            if let Some((name, mtl)) = current.take() {
+                materials.insert(name, mtl.to_material());
+            }
+            current = Some((tokens.rest("a material name")?, MtlMaterial::default()));
+            continue;
+        }
+
+        //All other statements describe the current material
+        let Some((_, mtl)) = current.as_mut() else {
+            return Err(tokens.error(format!("`{}` before any `newmtl`", keyword)));
+        };
+        match keyword {
+            "Kd" => mtl.kd = tokens.color()?,
+            "Ks" => mtl.ks = tokens.color()?,
+            "Ns" => mtl.ns = tokens.float()?,
+            "Ni" => mtl.ni = tokens.float()?,
+            "d" => mtl.d = tokens.float()?,
+            "Tr" => mtl.d = 1.0 - tokens.float()?,
+            "illum" => mtl.illum = tokens.integer()?,
+            //Other statements, such as texture maps, are not supported and are ignored
+            _ => {}
+        }
+    }
+    if let Some((name, mtl)) = current {
+        materials.insert(name, mtl.to_material());
+    }
+
+    Ok(materials)
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|source| ObjError::Io { file: path.to_path_buf(), source })
+}
+
+///The MTL properties used to choose a material
+struct MtlMaterial {
+    kd: Color,
+    ks: Color,
+    ns: f64,
+    ni: f64,
+    d: f64,
+    illum: i64,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        MtlMaterial {
+            kd: Color::new(0.5, 0.5, 0.5),
+            ks: Color::new_zeroes(),
+            ns: 0.0,
+            ni: 1.5,
+            d: 1.0,
+            illum: 2,
+        }
+    }
+}
+
+impl MtlMaterial {
+    fn to_material(self: &Self) -> Arc<dyn Material> {
+        if self.d < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            Arc::new(Dielectric::new(self.ni))
+        } else if matches!(self.illum, 3 | 5 | 8) {
+            //Convert the Phong specular exponent to a roughness
+            let fuzz = (2.0 / (self.ns.max(0.0) + 2.0)).sqrt();
+            Arc::new(Metal::new(self.ks, fuzz))
+        } else {
+            Arc::new(Lambertian::new(self.kd))
+        }
+    }
+}
+
+///Splits a line into whitespace separated tokens, ignoring comments, and
+/// reports errors against the line's position in its file.
+struct Tokens<'a> {
+    tokens: std::str::SplitWhitespace<'a>,
+    file: &'a Path,
+    line: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(line: &'a str, file: &'a Path, line_number: usize) -> Self {
+        let content = line.split('#').next().unwrap_or("");
+        Tokens { tokens: content.split_whitespace(), file, line: line_number }
+    }
+
+    fn next(self: &mut Self) -> Option<&'a str> {
+        self.tokens.next()
+    }
+
+    fn error(self: &Self, message: String) -> ObjError {
+        ObjError::Parse { file: self.file.to_path_buf(), line: self.line, message }
+    }
+
+    fn float(self: &mut Self) -> Result<f64, ObjError> {
+        let token = self.tokens.next().ok_or_else(|| self.error(String::from("expected a number")))?;
+        token.parse().map_err(|_| self.error(format!("`{}` is not a number", token)))
+    }
+
+    fn integer(self: &mut Self) -> Result<i64, ObjError> {
+        let token = self.tokens.next().ok_or_else(|| self.error(String::from("expected an integer")))?;
+        token.parse().map_err(|_| self.error(format!("`{}` is not an integer", token)))
+    }
+
+    fn vec3(self: &mut Self) -> Result<Vec3, ObjError> {
+        Ok(Vec3::new(self.float()?, self.float()?, self.float()?))
+    }
+
+    fn color(self: &mut Self) -> Result<Color, ObjError> {
+        Ok(Color::new(self.float()?, self.float()?, self.float()?))
+    }
+
+    ///The remainder of the line, which must not be empty
+    fn rest(self: &mut Self, what: &str) -> Result<String, ObjError> {
+        let rest: Vec<&str> = self.tokens.by_ref().collect();
+        if rest.is_empty() {
+            Err(self.error(format!("expected {}", what)))
+        } else {
+            Ok(rest.join(" "))
+        }
+    }
+}
+
+///A face corner: indices into the position, texture coordinate and normal lists
+type Corner = (usize, Option<usize>, Option<usize>);
+
+///The triangles of one group and material combination, with the OBJ's
+/// separately indexed positions, uvs and normals merged into single vertices.
+#[derive(Default)]
+struct MeshBuilder {
+    corners: HashMap<Corner, usize>,
+    positions: Vec<Vec3>,
+    uvs: Vec<Option<(f64, f64)>>,
+    normals: Vec<Option<Vec3>>,
+    indices: Vec<[usize; 3]>,
+}
+
+struct ObjParser<'a> {
+    file: &'a Path,
+    positions: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    normals: Vec<Vec3>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    group: String,
+    material: Option<String>,
+    //Meshes in the order they are first used
+    meshes: Vec<((String, Option<String>), MeshBuilder)>,
+}
+
+impl<'a> ObjParser<'a> {
+    fn new(file: &'a Path) -> Self {
+        ObjParser {
+            file,
+            positions: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            materials: HashMap::new(),
+            group: String::new(),
+            material: None,
+            meshes: Vec::new(),
+        }
+    }
+
+    fn parse_line(self: &mut Self, line: &str, line_number: usize) -> Result<(), ObjError> {
+        let mut tokens = Tokens::new(line, self.file, line_number);
+        let Some(keyword) = tokens.next() else { return Ok(()) };
+
+        match keyword {
+            "v" => self.positions.push(tokens.vec3()?),
+            "vt" => {
+                let u = tokens.float()?;
+                //v is optional and defaults to 0
+                let v = if let Some(token) = tokens.next() {
+                    token.parse().map_err(|_| tokens.error(format!("`{}` is not a number", token)))?
+                } else {
+                    0.0
+                };
+                self.uvs.push((u, v));
+            }
+            "vn" => self.normals.push(tokens.vec3()?),
+            "f" => self.parse_face(&mut tokens)?,
+            "g" | "o" => self.group = tokens.rest("a group name").unwrap_or_default(),
+            "usemtl" => {
+                let name = tokens.rest("a material name")?;
+                if !self.materials.contains_key(&name) {
+                    return Err(tokens.error(format!("unknown material `{}`", name)));
+                }
+                self.material = Some(name);
+            }
+            "mtllib" => {
+                //Material libraries are found relative to the .obj file
+                let directory = self.file.parent().unwrap_or(Path::new(""));
+                while let Some(name) = tokens.next() {
+                    self.materials.extend(load_mtl(directory.join(name))?);
+                }
+            }
+            //Other statements, such as smoothing groups, are not supported and are ignored
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn parse_face(self: &mut Self, tokens: &mut Tokens) -> Result<(), ObjError> {
+        let mut corners = Vec::new();
+        while let Some(token) = tokens.next() {
+            corners.push(self.parse_corner(token, tokens)?);
+        }
+        if corners.len() < 3 {
+            return Err(tokens.error(format!("a face needs at least 3 vertices, found {}", corners.len())));
+        }
+
+        let key = (self.group.clone(), self.material.clone());
+        let position = self.meshes.iter().position(|(mesh_key, _)| *mesh_key == key);
+        let index = position.unwrap_or_else(|| {
+            self.meshes.push((key, MeshBuilder::default()));
+            self.meshes.len() - 1
+        });
+        let mesh = &mut self.meshes[index].1;
+
+        let vertices: Vec<usize> = corners.into_iter().map(|corner| {
+            *mesh.corners.entry(corner).or_insert_with(|| {
+                mesh.positions.push(self.positions[corner.0]);
+                mesh.uvs.push(corner.1.map(|i| self.uvs[i]));
+                mesh.normals.push(corner.2.map(|i| self.normals[i]));
+                mesh.positions.len() - 1
+            })
+        }).collect();
+
+        //Triangulate the polygon as a fan around its first vertex
+        for i in 1..vertices.len() - 1 {
+            mesh.indices.push([vertices[0], vertices[i], vertices[i + 1]]);
+        }
+        Ok(())
+    }
+
+    ///Parse a face corner written as `v`, `v/vt`, `v//vn` or `v/vt/vn`
+    fn parse_corner(self: &Self, token: &str, tokens: &Tokens) -> Result<Corner, ObjError> {
+        let mut parts = token.split('/');
+        let position = parts.next().unwrap_or("");
+        let uv = parts.next().filter(|part| !part.is_empty());
+        let normal = parts.next().filter(|part| !part.is_empty());
+
+        let resolve = |index: &str, count: usize, what: &str| -> Result<usize, ObjError> {
+            let i: i64 = index.parse()
+                .map_err(|_| tokens.error(format!("`{}` is not a valid {} index", index, what)))?;
+            //Indices count from 1, or back from the end of the list if negative
+            let resolved = if i > 0 { i - 1 } else { count as i64 + i };
+            if i == 0 || resolved < 0 || resolved >= count as i64 {
+                Err(tokens.error(format!("{} index {} is out of range (there are {})", what, i, count)))
+            } else {
+                Ok(resolved as usize)
+            }
+        };
+
+        Ok((
+            resolve(position, self.positions.len(), "vertex")?,
+            uv.map(|i| resolve(i, self.uvs.len(), "texture coordinate")).transpose()?,
+            normal.map(|i| resolve(i, self.normals.len(), "normal")).transpose()?,
+        ))
+    }
+
+    fn finish(self: Self) -> HittableList {
+        let default_material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut list = HittableList::new_empty();
+
+        for ((_, material), mesh) in self.meshes {
+            let mat = material.map_or(default_material.clone(), |name| self.materials[&name].clone());
+            //A mesh is only smooth shaded or textured if every vertex has a normal or uv
+            let normals = mesh.normals.into_iter().collect::<Option<Vec<Vec3>>>().unwrap_or_default();
+            let uvs = mesh.uvs.into_iter().collect::<Option<Vec<(f64, f64)>>>().unwrap_or_default();
+            list.add(Arc::new(TriangleMesh::new(mesh.positions, normals, uvs, mesh.indices, mat)));
+        }
+        list
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::interval::Interval;
+    use crate::material::{ScatterRecord, ScatterSample};
+    use crate::ray::Ray;
+
+    //Write the files to a fresh directory under the system temporary
+    // directory, named for the process so that test runs do not share it
+    fn write_files(test_name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("rtiow_obj_{}_{}", std::process::id(), test_name));
+        fs::create_dir_all(&directory).unwrap();
+        for (name, contents) in files {
+            fs::write(directory.join(name), contents).unwrap();
+        }
+        directory
+    }
+
+    #[test]
+    fn load_obj_triangulates_quads_and_groups_by_material() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let directory = write_files("quads", &[
+            ("square.obj", "mtllib square.mtl\n\
+                v -1 -1 -1\nv 1 -1 -1\nv 1 1 -1\nv -1 1 -1\n\
+                vn 0 0 1\n\
+                g front\nusemtl red\nf 1//1 2//1 3//1 4//1\n\
+                g back\nusemtl glass\nf 4 3 2 1\n"),
+            ("square.mtl", "newmtl red\nKd 1 0 0\nnewmtl glass\nNi 1.5\nd 0.5\n"),
+        ]);
+
+        //Act
+        let list = load_obj(directory.join("square.obj")).unwrap();
+
+        //Assert
+        assert_eq!(2, list.objects.len());
+        let r = Ray::new(Vec3::new(0.5, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = list.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rng).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn load_mtl_picks_materials_that_scatter_as_described() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let directory = write_files("materials", &[
+            ("materials.mtl", "newmtl matte\nKd 0.8 0.2 0.1\nillum 2\n\
+                newmtl metal\nKd 0.1 0.1 0.1\nKs 0.9 0.8 0.7\nNs 100000\nillum 3\n\
+                newmtl dissolved\nNi 2.0\nd 0.5\n\
+                newmtl glass\nNi 2.0\nillum 7\n"),
+        ]);
+        //A ray meeting a surface facing +z at 45 degrees
+        let r_in = Ray::new(Vec3::new(-1.0, 0.0, 1.0), Vec3::new(1.0, 0.0, -1.0));
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+
+        //Act
+        let materials = load_mtl(directory.join("materials.mtl")).unwrap();
+        let mut scatter = |name: &str| -> ScatterRecord {
+            let hit_record = HitRecord::new(Vec3::new_zeroes(), normal, materials[name].clone(), 1.0, true);
+            materials[name].scatter(&r_in, &hit_record, &mut rng).unwrap()
+        };
+        let (matte, metal) = (scatter("matte"), scatter("metal"));
+        let glasses: Vec<ScatterRecord> = (0..100).flat_map(|_| [scatter("dissolved"), scatter("glass")]).collect();
+
+        //Assert
+        //Matte surfaces scatter Kd in proportion to the cosine about the normal
+        assert_eq!(Color::new(0.8, 0.2, 0.1), matte.attenuation);
+        let ScatterSample::Pdf(pdf) = matte.sample else { panic!("matte surfaces scatter diffusely") };
+        assert!((pdf.value(&normal, &mut rng) - 1.0 / std::f64::consts::PI).abs() < 1e-9);
+        //Shiny metal reflects Ks close to the mirror direction
+        assert_eq!(Color::new(0.9, 0.8, 0.7), metal.attenuation);
+        let ScatterSample::Pdf(pdf) = metal.sample else { panic!("rough metal scatters about the mirror direction") };
+        let mirror = Vec3::unit_vector(&Vec3::new(1.0, 0.0, 1.0));
+        assert!(Vec3::dot(&Vec3::unit_vector(&pdf.generate(&mut rng)), &mirror) > 0.99);
+        //Glass, whether dissolved or by illumination model, reflects or
+        // refracts everything, bending by Ni: sin 45 = 2 sin theta
+        for glass in glasses {
+            assert_eq!(Color::new(1.0, 1.0, 1.0), glass.attenuation);
+            let ScatterSample::Specular(scattered) = glass.sample else { panic!("glass scatters specularly") };
+            let direction = Vec3::unit_vector(scattered.direction());
+            if direction.z < 0.0 {
+                assert!((direction.x - 0.5f64.sqrt() / 2.0).abs() < 1e-9, "refracted along {:?}", direction);
+            } else {
+                assert!((direction - mirror).near_zero(), "reflected along {:?}", direction);
+            }
+        }
+    }
+
+    #[test]
+    fn load_obj_reports_file_and_line_of_bad_index() {
+        //Arrange
+        let directory = write_files("bad_index", &[
+            ("bad.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\n# a comment\nf 1 2 4\n"),
+        ]);
+
+        //Act
+        let result = load_obj(directory.join("bad.obj"));
+
+        //Assert
+        match result {
+            Err(ObjError::Parse { file, line, .. }) => {
+                assert_eq!(directory.join("bad.obj"), file);
+                assert_eq!(5, line);
+            }
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn load_obj_reports_unknown_material() {
+        //Arrange
+        let directory = write_files("unknown_material", &[
+            ("unknown.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl missing\nf 1 2 3\n"),
+        ]);
+
+        //Act
+        let result = load_obj(directory.join("unknown.obj"));
+
+        //Assert
+        assert!(matches!(result, Err(ObjError::Parse { line: 4, .. })));
+    }
+}
@@ -0,0 +1,53 @@
+use crate::vec3::Vec3;
+
+///An orthonormal basis: three perpendicular unit vectors u, v and w.
+///
+///Directions sampled about the z axis are turned to lie about w with
+/// transform, e.g. to scatter about a surface normal.
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    //Constructors
+    ///Create a basis with w along n, which need not be unit length
+    pub fn new(n: &Vec3) -> Self {
+        let w = Vec3::unit_vector(n);
+        //Any vector not parallel to w will do to find the other two
+        let a = if w.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let v = Vec3::unit_vector(&Vec3::cross(&w, &a));
+        let u = Vec3::cross(&w, &v);
+        Onb { u, v, w }
+    }
+
+    //Methods
+    ///Returns the vector with coordinates a in this basis
+    pub fn transform(self: &Self, a: &Vec3) -> Vec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn onb_is_orthonormal_with_w_along_n() {
+        //Arrange
+        let n = Vec3::new(0.3, -2.0, 1.1);
+
+        //Act
+        let onb = Onb::new(&n);
+
+        //Assert
+        assert!((Vec3::dot(&onb.w, &Vec3::unit_vector(&n)) - 1.0).abs() < 1e-12);
+        assert!(Vec3::dot(&onb.u, &onb.v).abs() < 1e-12);
+        assert!(Vec3::dot(&onb.u, &onb.w).abs() < 1e-12);
+        assert!(Vec3::dot(&onb.v, &onb.w).abs() < 1e-12);
+        assert!((onb.u.length() - 1.0).abs() < 1e-12 && (onb.v.length() - 1.0).abs() < 1e-12);
+    }
+}
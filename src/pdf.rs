@@ -0,0 +1,226 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use crate::onb::Onb;
+use crate::random::RenderRng;
+use crate::vec3::Vec3;
+
+///A probability density function over directions, which both picks random
+/// directions and tells how likely a direction was to be picked.
+///
+///Densities are with respect to solid angle. The rng is for densities that
+/// need random numbers to evaluate.
+pub trait Pdf {
+    ///The density of generate returning direction, which need not be unit length
+    fn value(self: &Self, direction: &Vec3, rng: &mut RenderRng) -> f64;
+
+    ///Returns a random direction, not necessarily of unit length
+    fn generate(self: &Self, rng: &mut RenderRng) -> Vec3;
+}
+
+///Every direction equally likely
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpherePdf;
+
+impl SpherePdf {
+    pub fn new() -> Self {
+        SpherePdf
+    }
+}
+
+impl Pdf for SpherePdf {
+    fn value(self: &Self, _direction: &Vec3, _rng: &mut RenderRng) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(self: &Self, rng: &mut RenderRng) -> Vec3 {
+        Vec3::random_unit_vector(rng)
+    }
+}
+
+///Directions in the hemisphere about w, weighted by the cosine of their angle
+/// to w. This matches the light scattered by a Lambertian surface with normal w.
+#[derive(Clone, Copy, Debug)]
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(w: &Vec3) -> Self {
+        CosinePdf { uvw: Onb::new(w) }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(self: &Self, direction: &Vec3, _rng: &mut RenderRng) -> f64 {
+        let cosine_theta = Vec3::dot(&Vec3::unit_vector(direction), &self.uvw.w);
+        (cosine_theta / PI).max(0.0)
+    }
+
+    fn generate(self: &Self, rng: &mut RenderRng) -> Vec3 {
+        //Pick a point uniformly on the unit disk and lift it onto the hemisphere
+        let p = Vec3::random_in_unit_disk(rng);
+        let z = (1.0 - p.x * p.x - p.y * p.y).max(0.0).sqrt();
+        self.uvw.transform(&Vec3::new(p.x, p.y, z))
+    }
+}
+
+///Reflections from a rough metal: the mirror reflection, a unit vector,
+/// nudged to a random point on a sphere of radius fuzz about its tip.
+#[derive(Clone, Copy, Debug)]
+pub struct FuzzyReflectionPdf {
+    reflected: Vec3,
+    fuzz: f64,
+}
+
+impl FuzzyReflectionPdf {
+    //Constructors
+    ///Create the density about the unit vector reflected.
+    ///
+    /// # Panics
+    /// If fuzz is not greater than 0; a perfect mirror has no density.
+    pub fn new(reflected: Vec3, fuzz: f64) -> Self {
+        assert!(fuzz > 0.0, "fuzz must be greater than 0");
+        FuzzyReflectionPdf { reflected, fuzz }
+    }
+
+    //Associated functions
+    ///The density of direction, found from where it meets the sphere of
+    /// nudged reflections. Points are uniform by area on the sphere, so each
+    /// meeting point adds t^2 / (cos * area), turning area into solid angle.
+    pub fn density(reflected: &Vec3, fuzz: f64, direction: &Vec3) -> f64 {
+        let unit_direction = Vec3::unit_vector(direction);
+        let b = Vec3::dot(&unit_direction, reflected);
+        let discriminant = b * b - (reflected.length_squared() - fuzz * fuzz);
+        if discriminant < 0.0 {
+            return 0.0;
+        }
+
+        let sqrtd = discriminant.sqrt();
+        let area = 4.0 * PI * fuzz * fuzz;
+        [b - sqrtd, b + sqrtd].iter()
+            .filter(|&&t| t > 1e-9)
+            .map(|&t| {
+                let normal = (t * unit_direction - *reflected) / fuzz;
+                let cosine = Vec3::dot(&normal, &unit_direction).abs();
+                if cosine < 1e-9 { 0.0 } else { t * t / (cosine * area) }
+            })
+            .sum()
+    }
+}
+
+impl Pdf for FuzzyReflectionPdf {
+    fn value(self: &Self, direction: &Vec3, _rng: &mut RenderRng) -> f64 {
+        FuzzyReflectionPdf::density(&self.reflected, self.fuzz, direction)
+    }
+
+    fn generate(self: &Self, rng: &mut RenderRng) -> Vec3 {
+        self.reflected + self.fuzz * Vec3::random_unit_vector(rng)
+    }
+}
+
+///The Henyey–Greenstein phase function about the direction of travel w, with
+/// anisotropy g. Directions are sampled exactly in proportion to it.
+#[derive(Clone, Copy, Debug)]
+pub struct HenyeyGreensteinPdf {
+    uvw: Onb,
+    g: f64,
+}
+
+impl HenyeyGreensteinPdf {
+    //Constructors
+    pub fn new(w: &Vec3, g: f64) -> Self {
+        HenyeyGreensteinPdf { uvw: Onb::new(w), g }
+    }
+
+    //Associated functions
+    ///The phase function for scattering through an angle with the given cosine
+    pub fn phase(g: f64, cos_theta: f64) -> f64 {
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+    }
+
+    ///Sample the cosine of the angle between the incoming and scattered
+    /// directions, by inverting the phase function's CDF at xi
+    fn sample_cos_theta(g: f64, xi: f64) -> f64 {
+        if g.abs() < 1e-3 {
+            return 1.0 - 2.0 * xi;
+        }
+        let sqr_term = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+        ((1.0 + g * g - sqr_term * sqr_term) / (2.0 * g)).clamp(-1.0, 1.0)
+    }
+}
+
+impl Pdf for HenyeyGreensteinPdf {
+    fn value(self: &Self, direction: &Vec3, _rng: &mut RenderRng) -> f64 {
+        let cos_theta = Vec3::dot(&Vec3::unit_vector(direction), &self.uvw.w);
+        HenyeyGreensteinPdf::phase(self.g, cos_theta)
+    }
+
+    fn generate(self: &Self, rng: &mut RenderRng) -> Vec3 {
+        let cos_theta = HenyeyGreensteinPdf::sample_cos_theta(self.g, rng.gen());
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f64>();
+        self.uvw.transform(&Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    //Estimate the integral of the density over the sphere of directions
+    fn integral_over_sphere(pdf: &dyn Pdf) -> f64 {
+        let mut rng = crate::random::new_rng(0);
+        let samples = 100000;
+        let uniform = SpherePdf::new();
+        let sum: f64 = (0..samples)
+            .map(|_| {
+                let direction = uniform.generate(&mut rng);
+                pdf.value(&direction, &mut rng) / uniform.value(&direction, &mut rng)
+            })
+            .sum();
+        sum / samples as f64
+    }
+
+    #[test]
+    fn densities_integrate_to_one() {
+        //Arrange
+        let w = Vec3::new(0.2, -1.0, 0.4);
+        let cosine = CosinePdf::new(&w);
+        let forwards = HenyeyGreensteinPdf::new(&w, 0.3);
+        let rough = FuzzyReflectionPdf::new(Vec3::unit_vector(&w), 0.6);
+
+        for pdf in [&cosine as &dyn Pdf, &forwards, &rough] {
+            //Act
+            let result = integral_over_sphere(pdf);
+
+            //Assert
+            assert!((result - 1.0).abs() < 0.02, "integral {}", result);
+        }
+    }
+
+    #[test]
+    fn cosine_pdf_generates_directions_about_w() {
+        //Arrange
+        let mut rng = crate::random::new_rng(0);
+        let w = Vec3::new(0.0, 0.0, 3.0);
+        let pdf = CosinePdf::new(&w);
+        let samples = 20000;
+
+        //Act
+        let mut sum = 0.0;
+        for _ in 0..samples {
+            let direction = pdf.generate(&mut rng);
+            assert!(direction.z >= 0.0);
+            sum += Vec3::unit_vector(&direction).z;
+        }
+
+        //Assert
+        //The mean cosine of a cosine-weighted hemisphere is 2/3
+        let mean = sum / samples as f64;
+        assert!((mean - 2.0 / 3.0).abs() < 0.01, "mean cosine {}", mean);
+    }
+}
@@ -0,0 +1,35 @@
+use rand::SeedableRng;
+use rand_pcg::Pcg64Mcg;
+
+///The random number generator used for all sampling in the renderer.
+///
+///PCG generators are fast, small and give the same sequence on every
+/// platform, so renders made with the same seed are identical.
+pub type RenderRng = Pcg64Mcg;
+
+///Create a generator seeded directly from a seed value
+pub fn new_rng(seed: u64) -> RenderRng {
+    RenderRng::seed_from_u64(seed)
+}
+
+///Create the generator for one sample of one pixel.
+///
+///Every (seed, x, y, sample) combination gets its own independent stream,
+/// so the result does not depend on the order in which pixels are rendered
+/// or on which thread renders them.
+pub fn pixel_rng(seed: u64, x: u32, y: u32, sample: u32) -> RenderRng {
+    let mut hash = splitmix64(seed);
+    hash = splitmix64(hash ^ x as u64);
+    hash = splitmix64(hash ^ y as u64);
+    hash = splitmix64(hash ^ sample as u64);
+    new_rng(hash)
+}
+
+//One step of the SplitMix64 generator, used here as a hash to mix the
+// pixel coordinates into well distributed seeds.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
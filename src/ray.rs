@@ -4,16 +4,25 @@ use crate::vec3::Vec3;
 pub struct Ray {
     orig: Vec3, 
     dir: Vec3,
+    tm: f64,    // the moment the ray is cast, within the camera's shutter interval
 } 
 
 ///A ray represented as the parametric vector equation: 
 /// ray = origin + (t * direction) 
 impl Ray {
     //Constructors
+    ///Create a ray cast at time 0
     pub fn new(origin: Vec3, direction: Vec3) -> Ray {
+        Ray::new_with_time(origin, direction, 0.0)
+    } 
+
+    ///Create a ray cast at the given time, which moving objects use to find
+    /// where they are
+    pub fn new_with_time(origin: Vec3, direction: Vec3, time: f64) -> Ray {
         Ray {
             orig: origin,
             dir: direction,
+            tm: time,
         }
     } 
 
@@ -26,6 +35,10 @@ impl Ray {
         &self.dir
     }
 
+    pub fn time(&self) -> f64 {
+        self.tm
+    }
+
     //Other methods
     /// Returns a vector locating the point on the ray at position t
     /// 
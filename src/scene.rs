@@ -0,0 +1,1127 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use image::ImageError;
+use serde::Deserialize;
+
+use crate::background::{Background, EnvironmentMap, GradientBackground, NoBackground, SolidBackground};
+use crate::camera::Camera;
+use crate::density::{DensityField, GridDensity, NoiseDensity};
+use crate::hittable::instance::Instance;
+use crate::hittable::medium::{ConstantMedium, HeterogeneousMedium};
+use crate::hittable::planar::{axis_aligned_box, Disk, Quad};
+use crate::hittable::triangle::Triangle;
+use crate::hittable::{Hittable, HittableList, Sphere};
+use crate::integrator::IntegratorKind;
+use crate::material::{Dielectric, DiffuseLight, HenyeyGreenstein, Isotropic, Lambertian, Material, Metal};
+use crate::obj::{load_obj, ObjError};
+use crate::transform::Transform;
+use crate::texture::{Checker, ImageTexture, MarbleTexture, NoiseTexture, SolidColor, Texture, TurbulenceTexture};
+use crate::vec3::{color::Color, Vec3};
+
+///A scene loaded from a scene description file: a camera, the world it
+/// images, and the size of the image to render.
+pub struct Scene {
+    pub camera: Camera,
+    pub world: HittableList,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub integrator: IntegratorKind, // the camera is already set up with it
+    pub depth_far: f64,             // where the depth view turns black
+}
+
+///An error loading a scene description file.
+///
+///The path locates the offending value in the file, e.g. `objects[2].radius`.
+#[derive(Debug)]
+pub enum SceneError {
+    ///The file could not be read
+    Io { file: PathBuf, source: io::Error },
+    ///The file is not a valid scene description
+    Invalid { file: PathBuf, path: String, message: String },
+    ///A mesh referenced by the scene could not be loaded
+    Mesh { file: PathBuf, path: String, source: ObjError },
+    ///An image referenced by the scene could not be loaded
+    Image { file: PathBuf, path: String, source: ImageError },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { file, source } => write!(f, "{}: {}", file.display(), source),
+            SceneError::Invalid { file, path, message } => write!(f, "{}: {}: {}", file.display(), path, message),
+            SceneError::Mesh { file, path, source } => write!(f, "{}: {}: {}", file.display(), path, source),
+            SceneError::Image { file, path, source } => write!(f, "{}: {}: {}", file.display(), path, source),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io { source, .. } => Some(source),
+            SceneError::Invalid { .. } => None,
+            SceneError::Mesh { source, .. } => Some(source),
+            SceneError::Image { source, .. } => Some(source),
+        }
+    }
+}
+
+///Load a scene from a TOML scene description file.
+///
+///A scene file has seven optional sections; anything left out takes the
+/// same default as the Camera:
+///```toml
+///[camera]
+///look_from = [-2.0, 2.0, 1.0]
+///look_at = [0.0, 0.0, -1.0]
+///vup = [0.0, 1.0, 0.0]
+///vfov = 20.0                  # degrees
+///defocus_angle = 0.6          # degrees
+///focus_dist = 3.4
+///shutter_open = 0.0           # moving objects are animated from time 0 to 1
+///shutter_close = 1.0
+///
+///[render]
+///width = 400
+///aspect_ratio = 1.7778        # or give the height
+///samples_per_pixel = 50
+///max_depth = 10               # leave out for no limit, ending paths by roulette alone
+///roulette_min_depth = 3       # bounces before Russian roulette may end a path
+///seed = 0
+///threads = 0                  # 0 means one per CPU core
+///integrator = "path"          # or a debug view of the first hit along each ray: "normals", 
+///                             #    "depth", "albedo", "uv", "object_id" or "bvh_cost"
+///depth_far = 20.0             # distance the depth view fades to black at; defaults
+///                             #    to twice camera.focus_dist
+///
+///[background]
+///type = "gradient"            # or "solid" (color), "none",
+///                             #    "environment" (path, rotation, intensity)
+///bottom = [1.0, 1.0, 1.0]     # defaults to the daylight sky
+///top = [0.5, 0.7, 1.0]
+///
+///[textures.checker]
+///type = "checker"             # or "solid" (color), "image" (path), "noise" (scale, seed),
+///                             #    "turbulence" (scale, depth, seed), "marble" (scale, color, seed)
+///scale = 0.32
+///even = [0.2, 0.3, 0.1]
+///odd = [0.9, 0.9, 0.9]
+///
+///[materials.ground]
+///type = "lambertian"          # or "metal" (albedo, fuzz), "dielectric" (refraction_index),
+///albedo = "checker"           #    "diffuse_light" (emit), "isotropic" (albedo),
+///                             #    "henyey_greenstein" (albedo, g)
+///                             # albedo is a color or the name of a texture
+///
+///[[objects]]
+///type = "sphere"              # or "triangle" (vertices, material), "quad" (q, u, v, material),
+///                             #    "disk" (center, normal, radius, material),
+///                             #    "box" (a, b, material), "mesh" (path),
+///                             #    "instance" (object, scale, rotate, translate),
+///                             #    "constant_medium" (boundary, density, material),
+///                             #    "heterogeneous_medium" (boundary, density, sigma_a, sigma_s, g)
+///center = [0.0, -100.5, -1.0]  # also give center2 for a sphere moving there by time 1
+///radius = 100.0
+///material = "ground"
+///```
+///An instance places a copy of another object, given inline, by scaling,
+/// then rotating, then translating it:
+///```toml
+///[[objects]]
+///type = "instance"
+///object = { type = "box", a = [0.0, 0.0, 0.0], b = [165.0, 330.0, 165.0], material = "white" }
+///rotate = { axis = [0.0, 1.0, 0.0], angle = 15.0 }    # degrees
+///translate = [265.0, 0.0, 295.0]
+///```
+///Lights repeat objects, usually emitters, that are sampled directly at 
+/// every hit that is not a perfect mirror or glass, to cut noise. Only 
+/// spheres, quads, disks and triangles can be lights:
+///```toml
+///[[lights]]
+///type = "quad"
+///q = [343.0, 554.0, 332.0]
+///u = [-130.0, 0.0, 0.0]
+///v = [0.0, 0.0, -105.0]
+///material = "light"
+///```
+///A heterogeneous medium takes its density from a grid of values filling
+/// its boundary's bounding box, x varying fastest, or from Perlin turbulence:
+///```toml
+///[[objects]]
+///type = "heterogeneous_medium"
+///boundary = { type = "sphere", center = [0.0, 1.0, 0.0], radius = 1.0, material = "white" }
+///density = { type = "noise", scale = 2.0, depth = 5, max = 4.0, seed = 1 }
+///                             # or { type = "grid", resolution = [nx, ny, nz], values = [...] }
+///sigma_a = [0.1, 0.1, 0.1]    # absorption and scattering per unit density
+///sigma_s = [0.9, 0.9, 0.9]
+///g = 0.5                      # Henyey-Greenstein anisotropy, 0 scatters evenly
+///```
+///Mesh, texture and environment image paths are relative to the scene file.
+pub fn load_scene(path: impl AsRef<Path>) -> Result<Scene, SceneError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)
+        .map_err(|source| SceneError::Io { file: path.to_path_buf(), source })?;
+    parse_scene(&source, path)
+}
+
+///Parse a scene description. The file path is used for error messages and
+/// to find meshes.
+pub fn parse_scene(source: &str, file: &Path) -> Result<Scene, SceneError> {
+    let deserializer = toml::Deserializer::new(source);
+    let scene_file: SceneFile = serde_path_to_error::deserialize(deserializer).map_err(|err| {
+        let path = err.path().to_string();
+        let inner = err.into_inner();
+        //Report the line as well, as the path may stop short of the bad value
+        let message = match inner.span() {
+            Some(span) => {
+                let line = source[..span.start].matches('\n').count() + 1;
+                format!("{} (line {})", inner.message().trim(), line)
+            }
+            None => inner.message().trim().to_string(),
+        };
+        SceneError::Invalid { file: file.to_path_buf(), path, message }
+    })?;
+
+    SceneBuilder { file }.build(scene_file)
+}
+
+//The scene file structures. Arrays of three numbers are points, vectors or colors.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    #[serde(default)]
+    camera: CameraSpec,
+    #[serde(default)]
+    render: RenderSpec,
+    background: Option<BackgroundSpec>,
+    #[serde(default)]
+    textures: BTreeMap<String, TextureSpec>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialSpec>,
+    #[serde(default)]
+    objects: Vec<ObjectSpec>,
+    #[serde(default)]
+    lights: Vec<ObjectSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct CameraSpec {
+    look_from: [f64; 3],
+    look_at: [f64; 3],
+    vup: [f64; 3],
+    vfov: f64,
+    defocus_angle: f64,
+    focus_dist: f64,
+    shutter_open: f64,
+    shutter_close: f64,
+}
+
+impl Default for CameraSpec {
+    fn default() -> Self {
+        CameraSpec {
+            look_from: [0.0, 0.0, 0.0],
+            look_at: [0.0, 0.0, -1.0],
+            vup: [0.0, 1.0, 0.0],
+            vfov: 90.0,
+            defocus_angle: 0.0,
+            focus_dist: 10.0,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RenderSpec {
+    width: u32,
+    height: Option<u32>,
+    aspect_ratio: f64,
+    samples_per_pixel: u32,
+    max_depth: Option<u32>,
+    roulette_min_depth: u32,
+    seed: u64,
+    threads: usize,
+    integrator: String,
+    depth_far: Option<f64>,
+}
+
+impl Default for RenderSpec {
+    fn default() -> Self {
+        RenderSpec {
+            width: 400,
+            height: None,
+            aspect_ratio: 16.0 / 9.0,
+            samples_per_pixel: 10,
+            max_depth: None,
+            roulette_min_depth: 3,
+            seed: 0,
+            threads: 0,
+            integrator: String::from("path"),
+            depth_far: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundSpec {
+    Solid { color: [f64; 3] },
+    Gradient { bottom: Option<[f64; 3]>, top: Option<[f64; 3]> },
+    None,
+    Environment {
+        path: PathBuf,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+}
+
+fn default_intensity() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureSpec {
+    Solid { color: [f64; 3] },
+    Checker { scale: f64, even: [f64; 3], odd: [f64; 3] },
+    Image { path: PathBuf },
+    Noise { scale: f64, #[serde(default)] seed: u64 },
+    Turbulence { scale: f64, #[serde(default = "default_turbulence_depth")] depth: u32, #[serde(default)] seed: u64 },
+    Marble { scale: f64, #[serde(default = "default_marble_color")] color: [f64; 3], #[serde(default)] seed: u64 },
+}
+
+fn default_turbulence_depth() -> u32 {
+    7
+}
+
+fn default_marble_color() -> [f64; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+///A material's albedo: either a color or the name of a texture
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "albedo must be a color [r, g, b] or the name of a texture")]
+enum AlbedoSpec {
+    Color([f64; 3]),
+    Texture(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialSpec {
+    Lambertian { albedo: AlbedoSpec },
+    Metal { albedo: AlbedoSpec, #[serde(default)] fuzz: f64 },
+    Dielectric { refraction_index: f64 },
+    DiffuseLight { emit: [f64; 3] },
+    Isotropic { albedo: AlbedoSpec },
+    HenyeyGreenstein { albedo: AlbedoSpec, g: f64 },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectSpec {
+    ///center2, if given, is where the sphere has moved to at time 1
+    Sphere { center: [f64; 3], center2: Option<[f64; 3]>, radius: f64, material: String },
+    Triangle { vertices: [[f64; 3]; 3], material: String },
+    Quad { q: [f64; 3], u: [f64; 3], v: [f64; 3], material: String },
+    Disk { center: [f64; 3], normal: [f64; 3], radius: f64, material: String },
+    Box { a: [f64; 3], b: [f64; 3], material: String },
+    ///Another object, scaled, then rotated, then translated
+    Instance {
+        object: Box<ObjectSpec>,
+        scale: Option<[f64; 3]>,
+        rotate: Option<RotateSpec>,
+        translate: Option<[f64; 3]>,
+    },
+    ///Fog or smoke filling another object, given inline. The material, 
+    /// usually isotropic, is the medium's phase function.
+    ConstantMedium { boundary: Box<ObjectSpec>, density: f64, material: String },
+    ///Cloud or smoke of varying density filling another object, given inline
+    HeterogeneousMedium {
+        boundary: Box<ObjectSpec>,
+        density: DensitySpec,
+        sigma_a: [f64; 3],
+        sigma_s: [f64; 3],
+        #[serde(default)]
+        g: f64,
+    },
+    Mesh { path: PathBuf },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum DensitySpec {
+    Grid { resolution: [usize; 3], values: Vec<f64> },
+    Noise {
+        scale: f64,
+        #[serde(default = "default_turbulence_depth")]
+        depth: u32,
+        #[serde(default = "default_max_density")]
+        max: f64,
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+fn default_max_density() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotateSpec {
+    axis: [f64; 3],
+    angle: f64,     // degrees
+}
+
+fn to_vec3(a: [f64; 3]) -> Vec3 {
+    Vec3::new(a[0], a[1], a[2])
+}
+
+fn to_color(a: [f64; 3]) -> Color {
+    Color::new(a[0], a[1], a[2])
+}
+
+fn is_positive(x: f64) -> bool {
+    x.is_finite() && x > 0.0
+}
+
+//Also rejects NaN, which fails every comparison
+fn is_non_negative(x: f64) -> bool {
+    x.is_finite() && x >= 0.0
+}
+
+///Checks the scene description and turns it into a Scene
+struct SceneBuilder<'a> {
+    file: &'a Path,
+}
+
+impl SceneBuilder<'_> {
+    fn invalid(self: &Self, path: impl Into<String>, message: impl Into<String>) -> SceneError {
+        SceneError::Invalid { file: self.file.to_path_buf(), path: path.into(), message: message.into() }
+    }
+
+    fn build(self: &Self, scene_file: SceneFile) -> Result<Scene, SceneError> {
+        let (image_width, image_height) = self.image_size(&scene_file.render)?;
+        let (integrator, depth_far) = self.integrator(&scene_file.camera, &scene_file.render)?;
+        let mut camera = self.camera(&scene_file.camera, &scene_file.render, image_width, image_height)?;
+        camera.set_integrator(integrator.debug_integrator(depth_far));
+        if let Some(spec) = &scene_file.background {
+            camera.set_background(self.background(spec)?);
+        }
+
+        let mut textures = BTreeMap::new();
+        for (name, spec) in &scene_file.textures {
+            textures.insert(name.as_str(), self.texture(name, spec)?);
+        }
+
+        let mut materials = BTreeMap::new();
+        for (name, spec) in &scene_file.materials {
+            materials.insert(name.as_str(), self.material(name, spec, &textures)?);
+        }
+
+        let mut world = HittableList::new_empty();
+        for (index, spec) in scene_file.objects.iter().enumerate() {
+            self.add_object(&mut world, format!("objects[{}]", index), spec, &materials)?;
+        }
+
+        let mut lights = HittableList::new_empty();
+        for (index, spec) in scene_file.lights.iter().enumerate() {
+            let path = format!("lights[{}]", index);
+            if !matches!(spec, ObjectSpec::Sphere { .. } | ObjectSpec::Quad { .. } | ObjectSpec::Disk { .. } | ObjectSpec::Triangle { .. }) {
+                return Err(self.invalid(path + ".type", "must be a sphere, quad, disk or triangle"));
+            }
+            self.add_object(&mut lights, path, spec, &materials)?;
+        }
+        if !lights.objects.is_empty() {
+            camera.set_lights(Arc::new(lights));
+        }
+
+        Ok(Scene { camera, world, image_width, image_height, integrator, depth_far })
+    }
+
+    fn image_size(self: &Self, render: &RenderSpec) -> Result<(u32, u32), SceneError> {
+        if render.width < 1 {
+            return Err(self.invalid("render.width", "must be at least 1"));
+        }
+        let height = match render.height {
+            Some(0) => return Err(self.invalid("render.height", "must be at least 1")),
+            Some(height) => height,
+            None => {
+                if !is_positive(render.aspect_ratio) {
+                    return Err(self.invalid("render.aspect_ratio", "must be greater than 0"));
+                }
+                //Calculate the height ensuring it is >=1
+                let height = render.width as f64 / render.aspect_ratio;
+                if height < 1.0 { 1 } else { height as u32 }
+            }
+        };
+        Ok((render.width, height))
+    }
+
+    fn camera(self: &Self, spec: &CameraSpec, render: &RenderSpec, image_width: u32, image_height: u32) -> Result<Camera, SceneError> {
+        if !(spec.vfov > 0.0 && spec.vfov < 180.0) {
+            return Err(self.invalid("camera.vfov", "must be between 0 and 180 degrees"));
+        }
+        if !is_positive(spec.focus_dist) {
+            return Err(self.invalid("camera.focus_dist", "must be greater than 0"));
+        }
+        if spec.defocus_angle.is_nan() || spec.defocus_angle < 0.0 {
+            return Err(self.invalid("camera.defocus_angle", "must not be negative"));
+        }
+        if spec.shutter_close.is_nan() || spec.shutter_close < spec.shutter_open {
+            return Err(self.invalid("camera.shutter_close", "must not be before camera.shutter_open"));
+        }
+        if spec.look_from == spec.look_at {
+            return Err(self.invalid("camera.look_at", "must differ from camera.look_from"));
+        }
+        //The camera's right is found from vup and the view direction
+        let view = to_vec3(spec.look_at) - to_vec3(spec.look_from);
+        let right = Vec3::cross(&to_vec3(spec.vup), &Vec3::unit_vector(&view)).length();
+        if right.is_nan() || right <= 1e-8 {
+            return Err(self.invalid("camera.vup", "must not be zero or parallel to the view direction"));
+        }
+        if render.samples_per_pixel < 1 {
+            return Err(self.invalid("render.samples_per_pixel", "must be at least 1"));
+        }
+
+        let mut camera = Camera::new(image_width as f64, image_height as f64);
+        camera.set_samples_per_pixel(render.samples_per_pixel);
+        camera.set_max_depth(render.max_depth);
+        camera.set_roulette_min_depth(render.roulette_min_depth);
+        camera.set_seed(render.seed);
+        camera.set_threads(render.threads);
+        camera.set_vfov(spec.vfov);
+        camera.set_look_from(to_vec3(spec.look_from));
+        camera.set_look_at(to_vec3(spec.look_at));
+        camera.set_vup(to_vec3(spec.vup));
+        camera.set_defocus_angle(spec.defocus_angle);
+        camera.set_focus_dist(spec.focus_dist);
+        camera.set_shutter(spec.shutter_open, spec.shutter_close);
+        Ok(camera)
+    }
+
+    //The integrator chosen, and the distance the depth view fades out at
+    fn integrator(self: &Self, camera: &CameraSpec, render: &RenderSpec) -> Result<(IntegratorKind, f64), SceneError> {
+        let integrator: IntegratorKind = render.integrator.parse()
+            .map_err(|message: String| self.invalid("render.integrator", message))?;
+        let depth_far = render.depth_far.unwrap_or(2.0 * camera.focus_dist);
+        if !is_positive(depth_far) {
+            return Err(self.invalid("render.depth_far", "must be greater than 0"));
+        }
+        Ok((integrator, depth_far))
+    }
+
+    fn background(self: &Self, spec: &BackgroundSpec) -> Result<Arc<dyn Background>, SceneError> {
+        let background: Arc<dyn Background> = match spec {
+            BackgroundSpec::Solid { color } => Arc::new(SolidBackground::new(to_color(*color))),
+            BackgroundSpec::Gradient { bottom, top } => {
+                let sky = GradientBackground::sky();
+                Arc::new(GradientBackground::new(bottom.map_or(sky.bottom, to_color), top.map_or(sky.top, to_color)))
+            }
+            BackgroundSpec::None => Arc::new(NoBackground),
+            BackgroundSpec::Environment { path, rotation, intensity } => {
+                if intensity.is_nan() || *intensity < 0.0 {
+                    return Err(self.invalid("background.intensity", "must not be negative"));
+                }
+                let map = EnvironmentMap::load(self.relative_path(path), *rotation, *intensity)
+                    .map_err(|source| SceneError::Image { 
+                        file: self.file.to_path_buf(), 
+                        path: String::from("background.path"), 
+                        source 
+                    })?;
+                Arc::new(map)
+            }
+        };
+        Ok(background)
+    }
+
+    //Files referenced by the scene are found relative to the scene file
+    fn relative_path(self: &Self, path: &Path) -> PathBuf {
+        self.file.parent().unwrap_or(Path::new("")).join(path)
+    }
+
+    fn texture(self: &Self, name: &str, spec: &TextureSpec) -> Result<Arc<dyn Texture>, SceneError> {
+        let path = format!("textures.{}", name);
+        if let TextureSpec::Checker { scale, .. } 
+            | TextureSpec::Noise { scale, .. } 
+            | TextureSpec::Turbulence { scale, .. } 
+            | TextureSpec::Marble { scale, .. } = spec {
+            if !is_positive(*scale) {
+                return Err(self.invalid(path + ".scale", "must be greater than 0"));
+            }
+        }
+        let texture: Arc<dyn Texture> = match spec {
+            TextureSpec::Solid { color } => Arc::new(SolidColor::new(to_color(*color))),
+            TextureSpec::Checker { scale, even, odd } => Arc::new(Checker::new_from_colors(*scale, to_color(*even), to_color(*odd))),
+            TextureSpec::Image { path: image_path } => {
+                let texture = ImageTexture::load(self.relative_path(image_path))
+                    .map_err(|source| SceneError::Image { file: self.file.to_path_buf(), path: path + ".path", source })?;
+                Arc::new(texture)
+            }
+            TextureSpec::Noise { scale, seed } => Arc::new(NoiseTexture::new(*seed, *scale)),
+            TextureSpec::Turbulence { scale, depth, seed } => Arc::new(TurbulenceTexture::new(*seed, *scale, *depth)),
+            TextureSpec::Marble { scale, color, seed } => Arc::new(MarbleTexture::new(*seed, *scale, to_color(*color))),
+        };
+        Ok(texture)
+    }
+
+    fn albedo(self: &Self,
+        textures: &BTreeMap<&str, Arc<dyn Texture>>,
+        path: &str,
+        albedo: &AlbedoSpec) -> Result<Arc<dyn Texture>, SceneError> {
+        match albedo {
+            AlbedoSpec::Color(color) => Ok(Arc::new(SolidColor::new(to_color(*color)))),
+            AlbedoSpec::Texture(name) => textures.get(name.as_str()).cloned()
+                .ok_or_else(|| self.invalid(format!("{}.albedo", path), format!("unknown texture `{}`", name))),
+        }
+    }
+
+    fn material(self: &Self,
+        name: &str,
+        spec: &MaterialSpec,
+        textures: &BTreeMap<&str, Arc<dyn Texture>>) -> Result<Arc<dyn Material>, SceneError> {
+        let path = format!("materials.{}", name);
+        let material: Arc<dyn Material> = match *spec {
+            MaterialSpec::Lambertian { ref albedo } => Arc::new(Lambertian::new_textured(self.albedo(textures, &path, albedo)?)),
+            MaterialSpec::Metal { ref albedo, fuzz } => {
+                if !(0.0..=1.0).contains(&fuzz) {
+                    return Err(self.invalid(path + ".fuzz", "must be between 0 and 1"));
+                }
+                Arc::new(Metal::new_textured(self.albedo(textures, &path, albedo)?, fuzz))
+            }
+            MaterialSpec::Dielectric { refraction_index } => {
+                if !is_positive(refraction_index) {
+                    return Err(self.invalid(path + ".refraction_index", "must be greater than 0"));
+                }
+                Arc::new(Dielectric::new(refraction_index))
+            }
+            MaterialSpec::DiffuseLight { emit } => Arc::new(DiffuseLight::new(to_color(emit))),
+            MaterialSpec::Isotropic { ref albedo } => Arc::new(Isotropic::new_textured(self.albedo(textures, &path, albedo)?)),
+            MaterialSpec::HenyeyGreenstein { ref albedo, g } => {
+                if !(-1.0..=1.0).contains(&g) {
+                    return Err(self.invalid(path + ".g", "must be between -1 and 1"));
+                }
+                Arc::new(HenyeyGreenstein::new_textured(self.albedo(textures, &path, albedo)?, g))
+            }
+        };
+        Ok(material)
+    }
+
+    fn find_material(self: &Self,
+        materials: &BTreeMap<&str, Arc<dyn Material>>,
+        path: &str,
+        name: &str) -> Result<Arc<dyn Material>, SceneError> {
+        materials.get(name).cloned()
+            .ok_or_else(|| self.invalid(format!("{}.material", path), format!("unknown material `{}`", name)))
+    }
+
+    fn add_object(self: &Self,
+        world: &mut HittableList,
+        path: String,
+        spec: &ObjectSpec,
+        materials: &BTreeMap<&str, Arc<dyn Material>>) -> Result<(), SceneError> {
+        match spec {
+            ObjectSpec::Sphere { center, center2, radius, material } => {
+                if !is_positive(*radius) {
+                    return Err(self.invalid(path + ".radius", format!("must be greater than 0, found {}", radius)));
+                }
+                let mat = self.find_material(materials, &path, material)?;
+                let sphere = match center2 {
+                    Some(center2) => Sphere::new_moving(to_vec3(*center), to_vec3(*center2), *radius, mat),
+                    None => Sphere::new(to_vec3(*center), *radius, mat),
+                };
+                world.add(Arc::new(sphere));
+            }
+            ObjectSpec::Triangle { vertices, material } => {
+                let [v0, v1, v2] = vertices.map(to_vec3);
+                if Vec3::cross(&(v1 - v0), &(v2 - v0)).near_zero() {
+                    return Err(self.invalid(path + ".vertices", "must not lie in a straight line"));
+                }
+                let mat = self.find_material(materials, &path, material)?;
+                world.add(Arc::new(Triangle::new(v0, v1, v2, mat)));
+            }
+            ObjectSpec::Quad { q, u, v, material } => {
+                let (u, v) = (to_vec3(*u), to_vec3(*v));
+                if Vec3::cross(&u, &v).near_zero() {
+                    return Err(self.invalid(path + ".v", "must not be parallel to u"));
+                }
+                let mat = self.find_material(materials, &path, material)?;
+                world.add(Arc::new(Quad::new(to_vec3(*q), u, v, mat)));
+            }
+            ObjectSpec::Disk { center, normal, radius, material } => {
+                if !is_positive(*radius) {
+                    return Err(self.invalid(path + ".radius", format!("must be greater than 0, found {}", radius)));
+                }
+                if to_vec3(*normal).near_zero() {
+                    return Err(self.invalid(path + ".normal", "must not be zero"));
+                }
+                let mat = self.find_material(materials, &path, material)?;
+                world.add(Arc::new(Disk::new(to_vec3(*center), to_vec3(*normal), *radius, mat)));
+            }
+            ObjectSpec::Box { a, b, material } => {
+                if (0..3).any(|axis| a[axis] == b[axis]) {
+                    return Err(self.invalid(path + ".b", "must differ from a along every axis"));
+                }
+                let mat = self.find_material(materials, &path, material)?;
+                world.add(Arc::new(axis_aligned_box(to_vec3(*a), to_vec3(*b), mat)));
+            }
+            ObjectSpec::Mesh { path: mesh_path } => {
+                let meshes = load_obj(self.relative_path(mesh_path))
+                    .map_err(|source| SceneError::Mesh { file: self.file.to_path_buf(), path: path + ".path", source })?;
+                for mesh in meshes.objects {
+                    world.add(mesh);
+                }
+            }
+            ObjectSpec::Instance { object, scale, rotate, translate } => {
+                let mut transform = Transform::identity();
+                //Tiny scales leave transforms that cannot be inverted accurately
+                if let Some(scale) = scale {
+                    if scale.iter().any(|factor| !(factor.is_finite() && factor.abs() >= 1e-6)) {
+                        return Err(self.invalid(path + ".scale", "must be a finite number, not near zero, along every axis"));
+                    }
+                    transform = transform.then(&Transform::scale(to_vec3(*scale)));
+                }
+                if let Some(RotateSpec { axis, angle }) = rotate {
+                    if !axis.iter().all(|a| a.is_finite()) || to_vec3(*axis).near_zero() {
+                        return Err(self.invalid(path + ".rotate.axis", "must be finite and not zero"));
+                    }
+                    if !angle.is_finite() {
+                        return Err(self.invalid(path + ".rotate.angle", "must be finite"));
+                    }
+                    transform = transform.then(&Transform::rotate(to_vec3(*axis), *angle));
+                }
+                if let Some(translate) = translate {
+                    if !translate.iter().all(|offset| offset.is_finite()) {
+                        return Err(self.invalid(path + ".translate", "must be finite"));
+                    }
+                    transform = transform.then(&Transform::translate(to_vec3(*translate)));
+                }
+
+                let mut inner = HittableList::new_empty();
+                self.add_object(&mut inner, path + ".object", object, materials)?;
+                world.add(Arc::new(Instance::new(Arc::new(inner), transform)));
+            }
+            ObjectSpec::ConstantMedium { boundary, density, material } => {
+                if !is_positive(*density) {
+                    return Err(self.invalid(path + ".density", "must be greater than 0"));
+                }
+                let mat = self.find_material(materials, &path, material)?;
+                let mut inner = HittableList::new_empty();
+                self.add_object(&mut inner, format!("{}.boundary", path), boundary, materials)?;
+                world.add(Arc::new(ConstantMedium::new_with_phase_function(Arc::new(inner), *density, mat)));
+            }
+            ObjectSpec::HeterogeneousMedium { boundary, density, sigma_a, sigma_s, g } => {
+                if !(-1.0..=1.0).contains(g) {
+                    return Err(self.invalid(path + ".g", "must be between -1 and 1"));
+                }
+                if sigma_a.iter().chain(sigma_s).any(|&sigma| !is_non_negative(sigma)) {
+                    return Err(self.invalid(path + ".sigma_a", "absorption and scattering must not be negative"));
+                }
+                let mut inner = HittableList::new_empty();
+                self.add_object(&mut inner, format!("{}.boundary", path), boundary, materials)?;
+
+                let density: Arc<dyn DensityField> = match density {
+                    DensitySpec::Grid { resolution, values } => {
+                        if resolution.contains(&0) {
+                            return Err(self.invalid(path + ".density.resolution", "must be at least 1 along every axis"));
+                        }
+                        let cells = resolution.iter().product::<usize>();
+                        if values.len() != cells {
+                            return Err(self.invalid(path + ".density.values", 
+                                format!("expected {} values for the resolution, found {}", cells, values.len())));
+                        }
+                        if values.iter().any(|&value| !is_non_negative(value)) {
+                            return Err(self.invalid(path + ".density.values", "must not be negative"));
+                        }
+                        Arc::new(GridDensity::new(inner.bounding_box(), *resolution, values.clone()))
+                    }
+                    DensitySpec::Noise { scale, depth, max, seed } => {
+                        if !is_positive(*scale) {
+                            return Err(self.invalid(path + ".density.scale", "must be greater than 0"));
+                        }
+                        if !is_non_negative(*max) {
+                            return Err(self.invalid(path + ".density.max", "must not be negative"));
+                        }
+                        Arc::new(NoiseDensity::new(*seed, *scale, *depth, *max))
+                    }
+                };
+                world.add(Arc::new(HeterogeneousMedium::new(Arc::new(inner), density, to_color(*sigma_a), to_color(*sigma_s), *g)));
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn error_path(source: &str) -> String {
+        match parse_scene(source, Path::new("test.toml")) {
+            Err(SceneError::Invalid { path, .. }) => path,
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn parse_scene_builds_camera_and_world() {
+        //Arrange
+        let source = r#"
+            [render]
+            width = 40
+            aspect_ratio = 2.0
+
+            [materials.ground]
+            type = "lambertian"
+            albedo = [0.8, 0.8, 0.0]
+
+            [materials.glass]
+            type = "dielectric"
+            refraction_index = 1.5
+
+            [[objects]]
+            type = "sphere"
+            center = [0.0, -100.5, -1.0]
+            radius = 100.0
+            material = "ground"
+
+            [[objects]]
+            type = "sphere"
+            center = [0.0, 0.0, -1.0]
+            radius = 0.5
+            material = "glass"
+        "#;
+
+        //Act
+        let scene = parse_scene(source, Path::new("test.toml")).unwrap();
+
+        //Assert
+        assert_eq!((40, 20), (scene.image_width, scene.image_height));
+        assert_eq!(2, scene.world.objects.len());
+    }
+
+    #[test]
+    fn default_scene_file_loads() {
+        //Arrange
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/default.toml");
+
+        //Act
+        let scene = load_scene(path).unwrap();
+
+        //Assert
+        assert_eq!((400, 225), (scene.image_width, scene.image_height));
+        assert_eq!(5, scene.world.objects.len());
+    }
+
+    #[test]
+    fn every_scene_file_loads() {
+        //Arrange
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+
+        for entry in fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|extension| extension == "toml") {
+                //Act
+                let result = load_scene(&path);
+
+                //Assert
+                assert!(result.is_ok(), "{}", result.err().unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_material_reports_object_path() {
+        //Arrange
+        let source = r#"
+            [[objects]]
+            type = "sphere"
+            center = [0.0, 0.0, -1.0]
+            radius = 0.5
+            material = "missing"
+        "#;
+
+        //Act
+        let result = error_path(source);
+
+        //Assert
+        assert_eq!("objects[0].material", result);
+    }
+
+    #[test]
+    fn unknown_texture_reports_material_path() {
+        //Arrange
+        let source = r#"
+            [textures.checker]
+            type = "checker"
+            scale = 0.5
+            even = [0.0, 0.0, 0.0]
+            odd = [1.0, 1.0, 1.0]
+
+            [materials.floor]
+            type = "lambertian"
+            albedo = "chequer"
+        "#;
+
+        //Act
+        let result = error_path(source);
+
+        //Assert
+        assert_eq!("materials.floor.albedo", result);
+    }
+
+    #[test]
+    fn light_that_cannot_be_sampled_reports_light_path() {
+        //Arrange
+        let source = r#"
+            [materials.light]
+            type = "diffuse_light"
+            emit = [4.0, 4.0, 4.0]
+
+            [[lights]]
+            type = "box"
+            a = [0.0, 0.0, 0.0]
+            b = [1.0, 1.0, 1.0]
+            material = "light"
+        "#;
+
+        //Act
+        let result = error_path(source);
+
+        //Assert
+        assert_eq!("lights[0].type", result);
+    }
+
+    #[test]
+    fn bad_noise_scale_reports_texture_path() {
+        //Arrange
+        let source = r#"
+            [textures.stone]
+            type = "marble"
+            scale = 0.0
+        "#;
+
+        //Act
+        let result = error_path(source);
+
+        //Assert
+        assert_eq!("textures.stone.scale", result);
+    }
+
+    #[test]
+    fn bad_noise_density_scale_reports_object_path() {
+        //Arrange
+        let source = r#"
+            [materials.white]
+            type = "lambertian"
+            albedo = [0.7, 0.7, 0.7]
+
+            [[objects]]
+            type = "heterogeneous_medium"
+            boundary = { type = "sphere", center = [0.0, 0.0, -1.0], radius = 0.5, material = "white" }
+            density = { type = "noise", scale = -1.0, depth = 5, max = 1.0, seed = 0 }
+            sigma_a = [0.1, 0.1, 0.1]
+            sigma_s = [0.9, 0.9, 0.9]
+        "#;
+
+        //Act
+        let result = error_path(source);
+
+        //Assert
+        assert_eq!("objects[0].density.scale", result);
+    }
+
+    #[test]
+    fn vup_along_the_view_direction_reports_camera_path() {
+        //Arrange
+        let source = r#"
+            [camera]
+            look_from = [0.0, 5.0, 0.0]
+            look_at = [0.0, 0.0, 0.0]
+            vup = [0.0, 1.0, 0.0]
+        "#;
+
+        //Act
+        let result = error_path(source);
+
+        //Assert
+        assert_eq!("camera.vup", result);
+    }
+
+    #[test]
+    fn unknown_integrator_reports_render_path() {
+        //Arrange
+        let source = r#"
+            [render]
+            integrator = "wireframe"
+        "#;
+
+        //Act
+        let result = error_path(source);
+
+        //Assert
+        assert_eq!("render.integrator", result);
+    }
+
+    #[test]
+    fn bad_depth_far_reports_render_path() {
+        //Arrange
+        let source = r#"
+            [render]
+            integrator = "depth"
+            depth_far = -1.0
+        "#;
+
+        //Act
+        let result = error_path(source);
+
+        //Assert
+        assert_eq!("render.depth_far", result);
+    }
+
+    #[test]
+    fn degenerate_triangles_and_boxes_report_object_path() {
+        //Arrange
+        let materials = r#"
+            [materials.red]
+            type = "lambertian"
+            albedo = [1.0, 0.0, 0.0]
+        "#;
+        let triangle = r#"
+            [[objects]]
+            type = "triangle"
+            vertices = [[0.0, 0.0, 0.0], [1.0, 1.0, 1.0], [2.0, 2.0, 2.0]]
+            material = "red"
+        "#;
+        let flat_box = r#"
+            [[objects]]
+            type = "box"
+            a = [0.0, 0.0, 0.0]
+            b = [1.0, 0.0, 1.0]
+            material = "red"
+        "#;
+
+        //Act
+        let triangle_result = error_path(&format!("{}{}", materials, triangle));
+        let box_result = error_path(&format!("{}{}", materials, flat_box));
+
+        //Assert
+        assert_eq!("objects[0].vertices", triangle_result);
+        assert_eq!("objects[0].b", box_result);
+    }
+
+    #[test]
+    fn bad_instance_transforms_report_object_path() {
+        //Arrange
+        let instance = |transform: &str| format!(r#"
+            [materials.red]
+            type = "lambertian"
+            albedo = [1.0, 0.0, 0.0]
+
+            [[objects]]
+            type = "instance"
+            object = {{ type = "sphere", center = [0.0, 0.0, 0.0], radius = 1.0, material = "red" }}
+            {}
+        "#, transform);
+        let cases = [
+            ("scale = [1.0, 1e-9, 1.0]", "objects[0].scale"),
+            ("scale = [nan, 1.0, 1.0]", "objects[0].scale"),
+            ("rotate = { axis = [0.0, 1.0, 0.0], angle = inf }", "objects[0].rotate.angle"),
+            ("translate = [0.0, -inf, 0.0]", "objects[0].translate"),
+        ];
+
+        for (transform, expected) in cases {
+            //Act
+            let result = error_path(&instance(transform));
+
+            //Assert
+            assert_eq!(expected, result);
+        }
+    }
+
+    #[test]
+    fn nan_medium_coefficient_reports_object_path() {
+        //Arrange
+        let source = r#"
+            [materials.white]
+            type = "lambertian"
+            albedo = [0.7, 0.7, 0.7]
+
+            [[objects]]
+            type = "heterogeneous_medium"
+            boundary = { type = "sphere", center = [0.0, 0.0, -1.0], radius = 0.5, material = "white" }
+            density = { type = "grid", resolution = [1, 1, 1], values = [1.0] }
+            sigma_a = [nan, 0.1, 0.1]
+            sigma_s = [0.9, 0.9, 0.9]
+        "#;
+
+        //Act
+        let result = error_path(source);
+
+        //Assert
+        assert_eq!("objects[0].sigma_a", result);
+    }
+
+    #[test]
+    fn bad_radius_reports_object_path() {
+        //Arrange
+        let source = r#"
+            [materials.red]
+            type = "lambertian"
+            albedo = [1.0, 0.0, 0.0]
+
+            [[objects]]
+            type = "sphere"
+            center = [0.0, 0.0, -1.0]
+            radius = 0.5
+            material = "red"
+
+            [[objects]]
+            type = "sphere"
+            center = [0.0, 0.0, -1.0]
+            radius = -0.5
+            material = "red"
+        "#;
+
+        //Act
+        let result = error_path(source);
+
+        //Assert
+        assert_eq!("objects[1].radius", result);
+    }
+
+    #[test]
+    fn missing_field_reports_path_and_field() {
+        //Arrange
+        let source = r#"
+            [materials.red]
+            type = "metal"
+            fuzz = 0.1
+        "#;
+
+        //Act
+        let result = parse_scene(source, Path::new("test.toml"));
+
+        //Assert
+        match result {
+            Err(SceneError::Invalid { path, message, .. }) => {
+                assert!(path.starts_with("materials.red"), "path was {}", path);
+                assert!(message.contains("albedo"), "message was {}", message);
+            }
+            _ => panic!("expected an invalid scene error"),
+        }
+    }
+}
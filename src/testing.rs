@@ -0,0 +1,34 @@
+//Fixtures shared between the unit tests of several modules
+
+use std::sync::Arc;
+
+use crate::hittable::Hittable;
+use crate::integrator::Integrator;
+use crate::material::{Lambertian, Material};
+use crate::random::RenderRng;
+use crate::ray::Ray;
+use crate::vec3::color::Color;
+
+///A plain mid-grey diffuse material
+pub(crate) fn grey() -> Arc<dyn Material> {
+    Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+}
+
+///The mean colour the integrator finds along r over many samples, an
+/// estimate of the colour it converges to
+pub(crate) fn mean_color(integrator: &dyn Integrator, r: &Ray, world: &dyn Hittable, samples: u32, rng: &mut RenderRng) -> Color {
+    let mut sum = Color::new_zeroes();
+    for _ in 0..samples {
+        sum = sum + integrator.ray_color(r, world, rng);
+    }
+    (1.0 / samples as f64) * sum
+}
+
+///Asserts that two integrators converge to the same colour along r, their
+/// means over many samples agreeing in the red channel to within 3%
+pub(crate) fn assert_same_mean_color(expected: &dyn Integrator, result: &dyn Integrator, r: &Ray, world: &dyn Hittable, rng: &mut RenderRng) {
+    let samples = 40000;
+    let expected = mean_color(expected, r, world, samples, rng).r();
+    let result = mean_color(result, r, world, samples, rng).r();
+    assert!((expected - result).abs() < 0.03 * expected, "expected {}, found {}", expected, result);
+}
@@ -0,0 +1,269 @@
+pub mod perlin;
+
+use std::fmt::Debug;
+use std::path::Path;
+use std::sync::Arc;
+
+use image::{DynamicImage, ImageResult, Rgb32FImage};
+
+use crate::vec3::{color::Color, Vec3};
+use perlin::Perlin;
+
+///A color that varies over a surface, looked up by the hit point's surface
+/// coordinates (u, v) and/or its position p.
+///
+///Textures are shared between render threads, so must be Send + Sync.
+pub trait Texture: Debug + Send + Sync {
+    fn value(self: &Self, u: f64, v: f64, p: &Vec3) -> Color;
+}
+
+///The same color everywhere
+#[derive(Clone, Copy, Debug)]
+pub struct SolidColor {
+    pub albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> Self {
+        SolidColor { albedo }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(self: &Self, _u: f64, _v: f64, _p: &Vec3) -> Color {
+        self.albedo
+    }
+}
+
+///A 3D checkerboard of cubes, scale units on a side, alternating between
+/// the even and odd textures.
+///
+///As the pattern is solid, any surface cutting through it is checkered,
+/// whatever its surface coordinates.
+#[derive(Clone, Debug)]
+pub struct Checker {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl Checker {
+    //Constructors
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Checker { inv_scale: 1.0 / scale, even, odd }
+    }
+
+    pub fn new_from_colors(scale: f64, even: Color, odd: Color) -> Self {
+        Checker::new(scale, Arc::new(SolidColor::new(even)), Arc::new(SolidColor::new(odd)))
+    }
+}
+
+impl Texture for Checker {
+    fn value(self: &Self, u: f64, v: f64, p: &Vec3) -> Color {
+        let x = (self.inv_scale * p.x).floor() as i64;
+        let y = (self.inv_scale * p.y).floor() as i64;
+        let z = (self.inv_scale * p.z).floor() as i64;
+
+        if (x + y + z).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+///An image wrapped over a surface using its (u, v) coordinates: u runs left
+/// to right across the image and v bottom to top.
+#[derive(Clone, Debug)]
+pub struct ImageTexture {
+    image: Rgb32FImage,     // linear color values
+}
+
+impl ImageTexture {
+    //Constructors
+    ///Create the texture from an image of linear color values
+    pub fn new(image: Rgb32FImage) -> Self {
+        ImageTexture { image }
+    }
+
+    ///Load the texture from an image file.
+    ///
+    ///Ordinary 8 and 16 bit images store gamma encoded colors, so are converted
+    /// back to linear values with the inverse of the gamma used for output.
+    /// Floating point images (.hdr, .exr) are already linear.
+    pub fn load(path: impl AsRef<Path>) -> ImageResult<Self> {
+        let image = image::open(path)?;
+        let is_linear = matches!(image, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_));
+        let mut image = image.into_rgb32f();
+        if !is_linear {
+            image.pixels_mut()
+                .flat_map(|pixel| pixel.0.iter_mut())
+                .for_each(|channel| *channel *= *channel);
+        }
+        Ok(ImageTexture::new(image))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(self: &Self, u: f64, v: f64, _p: &Vec3) -> Color {
+        let (width, height) = self.image.dimensions();
+        //With no image data, return solid cyan as a debugging aid
+        if width == 0 || height == 0 {
+            return Color::new(0.0, 1.0, 1.0);
+        }
+
+        //Clamp the coordinates to [0, 1], and flip v as image rows run top to bottom
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+
+        let column = ((u * width as f64) as u32).min(width - 1);
+        let row = ((v * height as f64) as u32).min(height - 1);
+        let pixel = self.image.get_pixel(column, row);
+        Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64)
+    }
+}
+
+///Smooth grey Perlin noise, with features roughly 1 / scale units across
+#[derive(Clone, Debug)]
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+}
+
+impl NoiseTexture {
+    pub fn new(seed: u64, scale: f64) -> Self {
+        NoiseTexture { noise: Perlin::new(seed), scale }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(self: &Self, _u: f64, _v: f64, p: &Vec3) -> Color {
+        //Map the noise from [-1, 1] to [0, 1]
+        let noise = 0.5 * (1.0 + self.noise.noise(&(self.scale * *p)));
+        noise * Color::new(1.0, 1.0, 1.0)
+    }
+}
+
+///Grey turbulence: depth octaves of Perlin noise summed, giving a rougher,
+/// cloudier pattern than NoiseTexture
+#[derive(Clone, Debug)]
+pub struct TurbulenceTexture {
+    noise: Perlin,
+    scale: f64,
+    depth: u32,
+}
+
+impl TurbulenceTexture {
+    pub fn new(seed: u64, scale: f64, depth: u32) -> Self {
+        TurbulenceTexture { noise: Perlin::new(seed), scale, depth }
+    }
+}
+
+impl Texture for TurbulenceTexture {
+    fn value(self: &Self, _u: f64, _v: f64, p: &Vec3) -> Color {
+        self.noise.turb(&(self.scale * *p), self.depth) * Color::new(1.0, 1.0, 1.0)
+    }
+}
+
+///Marble: bands running along z of the albedo color, their phase disturbed 
+/// by turbulence into veins
+#[derive(Clone, Debug)]
+pub struct MarbleTexture {
+    noise: Perlin,
+    scale: f64,
+    albedo: Color,
+}
+
+impl MarbleTexture {
+    //Constructors
+    pub fn new(seed: u64, scale: f64, albedo: Color) -> Self {
+        MarbleTexture { noise: Perlin::new(seed), scale, albedo }
+    }
+
+    //Associated constants
+    const TURBULENCE_DEPTH: u32 = 7;
+}
+
+impl Texture for MarbleTexture {
+    fn value(self: &Self, _u: f64, _v: f64, p: &Vec3) -> Color {
+        let phase = self.scale * p.z + 10.0 * self.noise.turb(p, MarbleTexture::TURBULENCE_DEPTH);
+        (0.5 * (1.0 + phase.sin())) * self.albedo
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checker_alternates_between_neighbouring_cubes() {
+        //Arrange
+        let checker = Checker::new_from_colors(0.5, Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0));
+
+        //Act
+        let even = checker.value(0.0, 0.0, &Vec3::new(0.25, 0.25, 0.25));
+        let odd = checker.value(0.0, 0.0, &Vec3::new(0.75, 0.25, 0.25));
+        let negative = checker.value(0.0, 0.0, &Vec3::new(-0.25, 0.25, 0.25));
+
+        //Assert
+        assert_eq!(1.0, even.r());
+        assert_eq!(0.0, odd.r());
+        assert_eq!(0.0, negative.r());
+    }
+
+    #[test]
+    fn noise_textures_give_bounded_values() {
+        //Arrange
+        let textures: [Box<dyn Texture>; 3] = [
+            Box::new(NoiseTexture::new(1, 4.0)),
+            Box::new(TurbulenceTexture::new(1, 4.0, 7)),
+            Box::new(MarbleTexture::new(1, 4.0, Color::new(1.0, 1.0, 1.0))),
+        ];
+
+        for texture in &textures {
+            for i in 0..200 {
+                //Act
+                let p = Vec3::new(i as f64 * 0.37, i as f64 * -0.11, i as f64 * 0.23);
+                let result = texture.value(0.0, 0.0, &p);
+
+                //Assert
+                //Turbulence sums octaves, so may slightly exceed 1
+                assert!((0.0..=2.0).contains(&result.r()), "value was {}", result.r());
+            }
+        }
+    }
+
+    #[test]
+    fn image_texture_maps_v_from_bottom_to_top() {
+        //Arrange
+        //A 2 x 2 image: each pixel's red channel is its column and its
+        // green channel is its row
+        let image = Rgb32FImage::from_fn(2, 2, |x, y| image::Rgb([x as f32, y as f32, 0.0]));
+        let texture = ImageTexture::new(image);
+        let p = Vec3::new_zeroes();
+
+        //Act
+        let bottom_left = texture.value(0.0, 0.0, &p);
+        let top_right = texture.value(1.0, 1.0, &p);
+
+        //Assert
+        assert_eq!((0.0, 1.0), (bottom_left.r(), bottom_left.g()));
+        assert_eq!((1.0, 0.0), (top_right.r(), top_right.g()));
+    }
+
+    #[test]
+    fn image_texture_load_linearises_8_bit_images() {
+        //Arrange
+        let path = std::env::temp_dir().join("rtiow_image_texture.png");
+        image::RgbImage::from_pixel(1, 1, image::Rgb([255, 0, 127])).save(&path).unwrap();
+
+        //Act
+        let texture = ImageTexture::load(&path).unwrap();
+        let result = texture.value(0.5, 0.5, &Vec3::new_zeroes());
+
+        //Assert
+        assert_eq!((1.0, 0.0), (result.r(), result.g()));
+        assert!((result.b() - 0.2481).abs() < 1e-3, "blue was {}", result.b());
+    }
+}
@@ -0,0 +1,148 @@
+use rand::seq::SliceRandom;
+
+use crate::random::new_rng;
+use crate::vec3::Vec3;
+
+const POINT_COUNT: usize = 256;
+
+///Perlin gradient noise: a smooth, repeatable pseudo-random function of
+/// position with values in [-1, 1].
+///
+///A random unit gradient vector is hashed to each integer lattice point,
+/// and the noise inside each lattice cell is interpolated from the eight
+/// gradients at its corners. The same seed always gives the same noise.
+#[derive(Clone, Debug)]
+pub struct Perlin {
+    randvec: Vec<Vec3>,     // POINT_COUNT random unit gradient vectors
+    perm_x: Vec<usize>,     // permutations of 0..POINT_COUNT hashing the
+    perm_y: Vec<usize>,     //  lattice coordinates to a gradient
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    //Constructors
+    pub fn new(seed: u64) -> Self {
+        let mut rng = new_rng(seed);
+        let randvec = (0..POINT_COUNT)
+            .map(|_| Vec3::unit_vector(&Vec3::new_random_in_range(-1.0, 1.0, &mut rng)))
+            .collect();
+
+        let mut permute = || {
+            let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+            perm.shuffle(&mut rng);
+            perm
+        };
+        let perm_x = permute();
+        let perm_y = permute();
+        let perm_z = permute();
+
+        Perlin { randvec, perm_x, perm_y, perm_z }
+    }
+
+    //Methods
+    ///The noise at p, in [-1, 1]. It is 0 at every integer lattice point.
+    pub fn noise(self: &Self, p: &Vec3) -> f64 {
+        let u = p.x - p.x.floor();
+        let v = p.y - p.y.floor();
+        let w = p.z - p.z.floor();
+
+        let i = p.x.floor() as i64;
+        let j = p.y.floor() as i64;
+        let k = p.z.floor() as i64;
+
+        //Find the gradients at the corners of the lattice cell containing p
+        let mut c = [[[Vec3::new_zeroes(); 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    *corner = self.randvec[
+                        self.perm_x[((i + di as i64) & 255) as usize]
+                        ^ self.perm_y[((j + dj as i64) & 255) as usize]
+                        ^ self.perm_z[((k + dk as i64) & 255) as usize]
+                    ];
+                }
+            }
+        }
+
+        Perlin::perlin_interp(&c, u, v, w)
+    }
+
+    ///Turbulence: the sum of depth octaves of noise, each at twice the
+    /// frequency and half the weight of the last.
+    pub fn turb(self: &Self, p: &Vec3, depth: u32) -> f64 {
+        let mut accum = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+
+        for _ in 0..depth {
+            accum += weight * self.noise(&temp_p);
+            weight *= 0.5;
+            temp_p = 2.0 * temp_p;
+        }
+
+        accum.abs()
+    }
+
+    //Associated functions
+    //Trilinearly interpolate the corner gradients' contributions across the
+    // cell, after Hermite smoothing (u, v, w) to hide the lattice.
+    fn perlin_interp(c: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+
+        let mut accum = 0.0;
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, corner) in row.iter().enumerate() {
+                    let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+                    let weight_v = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * Vec3::dot(corner, &weight_v);
+                }
+            }
+        }
+
+        accum
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn noise_is_zero_at_lattice_points_and_bounded_between() {
+        //Arrange
+        let perlin = Perlin::new(7);
+
+        //Act
+        let lattice = perlin.noise(&Vec3::new(3.0, -2.0, 5.0));
+        let samples: Vec<f64> = (0..1000)
+            .map(|i| perlin.noise(&Vec3::new(i as f64 * 0.173, i as f64 * 0.071, i as f64 * -0.113)))
+            .collect();
+
+        //Assert
+        assert_eq!(0.0, lattice);
+        assert!(samples.iter().all(|n| (-1.0..=1.0).contains(n)));
+        assert!(samples.iter().any(|n| n.abs() > 0.1));
+    }
+
+    #[test]
+    fn noise_with_same_seed_is_identical() {
+        //Arrange
+        let p = Vec3::new(1.3, 4.7, -0.2);
+
+        //Act
+        let first = Perlin::new(42).noise(&p);
+        let second = Perlin::new(42).noise(&p);
+        let other = Perlin::new(43).noise(&p);
+
+        //Assert
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::aabb::Aabb;
+use crate::vec3::Vec3;
+
+type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+///An affine transformation, stored as a 4x4 matrix together with its inverse.
+///
+///Points are transformed by the matrix, vectors by its upper 3x3 part, and
+/// normals by the inverse-transpose so they stay perpendicular to surfaces
+/// under non-uniform scaling.
+///
+///Transforms are built from translate, rotate and scale and combined with
+/// then, which keeps the inverse up to date without a general matrix inversion.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    m: Matrix,
+    inv: Matrix,
+}
+
+impl Transform {
+    //Constructors
+    pub fn identity() -> Self {
+        Transform { m: IDENTITY, inv: IDENTITY }
+    }
+
+    ///Create a transform from an affine matrix, given row by row, whose bottom
+    /// row is [0, 0, 0, 1].
+    ///
+    ///Returns None if the matrix is not affine or cannot be inverted.
+    pub fn new(m: [[f64; 4]; 4]) -> Option<Self> {
+        if m[3] != [0.0, 0.0, 0.0, 1.0] {
+            return None;
+        }
+
+        //Invert the upper 3x3 part by cofactors
+        let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+        let det = m[0][0] * cofactor(1, 2, 1, 2) - m[0][1] * cofactor(1, 2, 0, 2) + m[0][2] * cofactor(1, 2, 0, 1);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let mut inv = IDENTITY;
+        inv[0][0] = cofactor(1, 2, 1, 2) / det;
+        inv[0][1] = -cofactor(0, 2, 1, 2) / det;
+        inv[0][2] = cofactor(0, 1, 1, 2) / det;
+        inv[1][0] = -cofactor(1, 2, 0, 2) / det;
+        inv[1][1] = cofactor(0, 2, 0, 2) / det;
+        inv[1][2] = -cofactor(0, 1, 0, 2) / det;
+        inv[2][0] = cofactor(1, 2, 0, 1) / det;
+        inv[2][1] = -cofactor(0, 2, 0, 1) / det;
+        inv[2][2] = cofactor(0, 1, 0, 1) / det;
+
+        //The inverse translation undoes the translation after the 3x3 part is undone
+        for inv_row in inv.iter_mut().take(3) {
+            inv_row[3] = -(0..3).map(|k| inv_row[k] * m[k][3]).sum::<f64>();
+        }
+
+        Some(Transform { m, inv })
+    }
+
+    pub fn translate(offset: Vec3) -> Self {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for axis in 0..3 {
+            m[axis][3] = offset[axis];
+            inv[axis][3] = -offset[axis];
+        }
+        Transform { m, inv }
+    }
+
+    ///Scale by factor along each axis.
+    ///
+    /// # Panics
+    /// If any factor is zero or not finite.
+    pub fn scale(factor: Vec3) -> Self {
+        let factors = [factor.x, factor.y, factor.z];
+        assert!(factors.iter().all(|f| f.is_finite() && *f != 0.0), "scale factors must be finite and not zero");
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for axis in 0..3 {
+            m[axis][axis] = factor[axis];
+            inv[axis][axis] = 1.0 / factor[axis];
+        }
+        Transform { m, inv }
+    }
+
+    ///Rotate anticlockwise by angle degrees about axis, looking down the axis
+    /// towards the origin.
+    pub fn rotate(axis: Vec3, angle: f64) -> Self {
+        let a = Vec3::unit_vector(&axis);
+        let (sin_theta, cos_theta) = angle.to_radians().sin_cos();
+        let k = 1.0 - cos_theta;
+
+        //Rodrigues' rotation formula
+        let m = [
+            [cos_theta + a.x * a.x * k, a.x * a.y * k - a.z * sin_theta, a.x * a.z * k + a.y * sin_theta, 0.0],
+            [a.y * a.x * k + a.z * sin_theta, cos_theta + a.y * a.y * k, a.y * a.z * k - a.x * sin_theta, 0.0],
+            [a.z * a.x * k - a.y * sin_theta, a.z * a.y * k + a.x * sin_theta, cos_theta + a.z * a.z * k, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        //A rotation's inverse is its transpose
+        Transform { m, inv: Transform::transpose(&m) }
+    }
+
+    //Methods
+    ///Returns the transform applying self, then next
+    pub fn then(self: &Self, next: &Transform) -> Transform {
+        Transform {
+            m: Transform::multiply(&next.m, &self.m),
+            inv: Transform::multiply(&self.inv, &next.inv),
+        }
+    }
+
+    ///Returns the transform whose matrix is interpolated linearly between
+    /// self, at time 0, and other, at time 1. 
+    /// 
+    ///Translation and scaling interpolate exactly; rotations between the
+    /// two are not rigid and shrink the object part way, so keep them small.
+    /// Returns None if the interpolated matrix cannot be inverted.
+    pub fn lerp(self: &Self, other: &Transform, time: f64) -> Option<Transform> {
+        let mut m = self.m;
+        for (row, other_row) in m.iter_mut().zip(&other.m) {
+            for (value, other_value) in row.iter_mut().zip(other_row) {
+                *value += time * (other_value - *value);
+            }
+        }
+        Transform::new(m)
+    }
+
+    ///Returns the transform undoing self
+    pub fn inverse(self: &Self) -> Transform {
+        Transform { m: self.inv, inv: self.m }
+    }
+
+    ///The matrix, row by row
+    pub fn matrix(self: &Self) -> &[[f64; 4]; 4] {
+        &self.m
+    }
+
+    pub fn transform_point(self: &Self, p: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        )
+    }
+
+    ///Transform a direction, which is unaffected by translation
+    pub fn transform_vector(self: &Self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    ///Transform a surface normal by the inverse-transpose. The result is not
+    /// unit length.
+    pub fn transform_normal(self: &Self, n: &Vec3) -> Vec3 {
+        let inv = &self.inv;
+        Vec3::new(
+            inv[0][0] * n.x + inv[1][0] * n.y + inv[2][0] * n.z,
+            inv[0][1] * n.x + inv[1][1] * n.y + inv[2][1] * n.z,
+            inv[0][2] * n.x + inv[1][2] * n.y + inv[2][2] * n.z,
+        )
+    }
+
+    ///Returns the axis-aligned box enclosing the transformed box, found by
+    /// transforming all eight of its corners.
+    pub fn transform_box(self: &Self, bbox: &Aabb) -> Aabb {
+        if bbox.x.min > bbox.x.max || bbox.y.min > bbox.y.max || bbox.z.min > bbox.z.max {
+            return Aabb::EMPTY;
+        }
+
+        let mut result = Aabb::EMPTY;
+        for i in 0..8 {
+            let corner = Vec3::new(
+                if i & 1 == 0 { bbox.x.min } else { bbox.x.max },
+                if i & 2 == 0 { bbox.y.min } else { bbox.y.max },
+                if i & 4 == 0 { bbox.z.min } else { bbox.z.max },
+            );
+            let p = self.transform_point(&corner);
+            result = Aabb::new_enclosing(&result, &Aabb::new_from_points(p, p));
+        }
+        result
+    }
+
+    //Associated functions
+    fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+        let mut result = [[0.0; 4]; 4];
+        for (row, result_row) in result.iter_mut().enumerate() {
+            for (column, value) in result_row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| a[row][k] * b[k][column]).sum();
+            }
+        }
+        result
+    }
+
+    fn transpose(m: &Matrix) -> Matrix {
+        let mut result = [[0.0; 4]; 4];
+        for (row, result_row) in result.iter_mut().enumerate() {
+            for (column, value) in result_row.iter_mut().enumerate() {
+                *value = m[column][row];
+            }
+        }
+        result
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_near(expected: Vec3, result: Vec3) {
+        assert!((expected - result).length() < 1e-9, "expected {:?}, found {:?}", expected, result);
+    }
+
+    #[test]
+    fn combined_transform_is_undone_by_its_inverse() {
+        //Arrange
+        let transform = Transform::scale(Vec3::new(2.0, 3.0, 0.5))
+            .then(&Transform::rotate(Vec3::new(1.0, 1.0, 0.0), 40.0))
+            .then(&Transform::translate(Vec3::new(1.0, -2.0, 3.0)));
+        let p = Vec3::new(0.3, -0.7, 1.9);
+
+        //Act
+        let result = transform.inverse().transform_point(&transform.transform_point(&p));
+
+        //Assert
+        assert_near(p, result);
+    }
+
+    #[test]
+    fn rotate_turns_anticlockwise_about_axis() {
+        //Arrange
+        let transform = Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 90.0);
+
+        //Act
+        let result = transform.transform_point(&Vec3::new(1.0, 0.0, 0.0));
+
+        //Assert
+        assert_near(Vec3::new(0.0, 1.0, 0.0), result);
+    }
+
+    #[test]
+    fn new_inverts_affine_matrix() {
+        //Arrange
+        let expected = Transform::rotate(Vec3::new(0.2, 1.0, -0.4), 70.0)
+            .then(&Transform::scale(Vec3::new(1.0, 4.0, 2.0)))
+            .then(&Transform::translate(Vec3::new(5.0, 6.0, 7.0)));
+
+        //Act
+        let result = Transform::new(*expected.matrix()).unwrap();
+        let p = Vec3::new(1.0, 2.0, 3.0);
+
+        //Assert
+        assert_near(expected.inverse().transform_point(&p), result.inverse().transform_point(&p));
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scale() {
+        //Arrange
+        //A surface containing the vector (1, 1, 0), with normal (1, -1, 0)
+        let transform = Transform::scale(Vec3::new(4.0, 1.0, 1.0));
+        let tangent = Vec3::new(1.0, 1.0, 0.0);
+        let normal = Vec3::new(1.0, -1.0, 0.0);
+
+        //Act
+        let result = Vec3::dot(&transform.transform_vector(&tangent), &transform.transform_normal(&normal));
+
+        //Assert
+        assert!(result.abs() < 1e-12);
+    }
+}
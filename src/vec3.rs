@@ -1,8 +1,10 @@
 pub mod color;
 
-use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
+use std::ops::{Add, AddAssign, Div, Index, Mul, Neg, Sub};
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
+
+use crate::random::RenderRng;
 
 #[derive(Copy, Clone, PartialEq, Debug )]
 pub struct Vec3 {
@@ -26,8 +28,7 @@ impl Vec3{
     } 
 
     ///Create a new Vec3 with coordinates in the range 0..1
-    pub fn new_random() -> Vec3 {
-        let mut rng = thread_rng();
+    pub fn new_random(rng: &mut RenderRng) -> Vec3 {
         Vec3 {
             x: rng.gen_range(0f64..1f64), 
             y: rng.gen_range(0f64..1f64), 
@@ -36,8 +37,7 @@ impl Vec3{
     }
 
     ///Create a new Vec3 with coordinates in the range min..max 
-    pub fn new_random_in_range(min: f64, max: f64) -> Vec3 {
-        let mut rng = thread_rng();
+    pub fn new_random_in_range(min: f64, max: f64, rng: &mut RenderRng) -> Vec3 {
         Vec3 {
             x: rng.gen_range(min..max), 
             y: rng.gen_range(min..max), 
@@ -79,10 +79,10 @@ impl Vec3{
         Vec3::new(u.x * inv_length, u.y * inv_length, u.z * inv_length)
     }
 
-    pub fn random_in_unit_sphere() -> Vec3 {
+    pub fn random_in_unit_sphere(rng: &mut RenderRng) -> Vec3 {
         loop {
             //Get a random vector inside the unit cube
-            let p = Vec3::new_random_in_range(-1.0, 1.0); 
+            let p = Vec3::new_random_in_range(-1.0, 1.0, rng); 
             //Return the first vector that is also inside the unit sphere
             // rejecting vectors that are not. 
             if p.length_squared() < 1.0 {
@@ -91,12 +91,26 @@ impl Vec3{
         }
     }
 
-    pub fn random_unit_vector() -> Vec3 {
-        Vec3::unit_vector(&Vec3::random_in_unit_sphere())
+    ///Returns a random vector inside the unit disk which lies in the xy-plane 
+    /// and is centered on the origin.
+    pub fn random_in_unit_disk(rng: &mut RenderRng) -> Vec3 {
+        loop {
+            //Get a random vector inside the unit square
+            let p = Vec3::new(rng.gen_range(-1f64..1f64), rng.gen_range(-1f64..1f64), 0f64);
+            //Return the first vector that is also inside the unit disk
+            // rejecting vectors that are not. 
+            if p.length_squared() < 1.0 {
+                break p
+            }
+        }
+    }
+
+    pub fn random_unit_vector(rng: &mut RenderRng) -> Vec3 {
+        Vec3::unit_vector(&Vec3::random_in_unit_sphere(rng))
     }
 
-    pub fn random_on_hemisphere(normal :&Vec3) -> Vec3 {
-        let on_unit_sphere = Vec3::random_unit_vector();
+    pub fn random_on_hemisphere(normal :&Vec3, rng: &mut RenderRng) -> Vec3 {
+        let on_unit_sphere = Vec3::random_unit_vector(rng);
         if Vec3::dot(&on_unit_sphere, normal) > 0.0 {
             on_unit_sphere
         } else {
@@ -191,6 +205,20 @@ impl Sub for Vec3 {
     }
 }
 
+//Indexing gives access to the coordinates by axis number: 0 = x, 1 = y, 2 = z
+impl Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 axis index out of range: {}", axis),
+        }
+    }
+}
+
 impl Div<f64> for Vec3 {
     type Output = Self;
 
@@ -367,7 +395,8 @@ mod test {
    #[test] 
    fn near_zero_catches_single_coordinates_near_zero() {
     //Arrange 
-    let v1 = Vec3::new_random_in_range(-1e-8 + 1e-9, 1e-8); 
+    let mut rng = crate::random::new_rng(0);
+    let v1 = Vec3::new_random_in_range(-1e-8 + 1e-9, 1e-8, &mut rng); 
     let expected = true;
 
     //Act
@@ -377,6 +406,20 @@ mod test {
     assert_eq!(expected, result);
    }
 
+   #[test]
+   fn random_in_unit_disk_lies_inside_unit_disk() {
+    //Arrange 
+    let expected = (true, 0.0);
+
+    //Act
+    let mut rng = crate::random::new_rng(0);
+    let v = Vec3::random_in_unit_disk(&mut rng);
+    let result = (v.length_squared() < 1.0, v.z);
+
+    //Assert 
+    assert_eq!(expected, result);
+   }
+
    #[test]
    
    fn near_zero_works_with_range_boundaries() {
@@ -7,7 +7,7 @@ use crate::interval::Interval;
 //  We can control which of the public functions of Vec3 are accessible
 //  when using it as a Color. 
 //  We can also add new, Color only public functions. 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Color(Vec3);
 
 impl Color { 
@@ -34,6 +34,11 @@ impl Color {
         self.0.z
     }
 
+    ///The perceived brightness of the colour, using the Rec. 709 weights
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r() + 0.7152 * self.g() + 0.0722 * self.b()
+    }
+
     //Associated functions
     pub fn output_color(&self) -> [u8; 3] {    
         //Move from [0 .. 1] colour values to [0 .. 255] colour values. 
@@ -103,7 +108,8 @@ mod test {
     fn output_color_converts_from_f64_to_u8_color_channels() {
         //Arrange 
         let (first, second, third) = (0.1, 0.2, 0.3);
-        let expected = [25u8, 51u8, 76u8];
+        //Values are gamma corrected (gamma 2) before conversion
+        let expected = [80u8, 114u8, 140u8];
 
         //Act
         let c : Color = Color::new(first, second, third);